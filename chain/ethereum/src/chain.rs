@@ -24,7 +24,7 @@ use graph::{
     prelude::{
         async_trait, error, lazy_static, o, serde_json as json, web3::types::H256, BlockNumber,
         ChainStore, EthereumBlockWithCalls, Future01CompatExt, Logger, LoggerFactory,
-        MetricsRegistry, NodeId, SubgraphStore,
+        MetricsRegistry, NodeId, StoreError, SubgraphStore,
     },
 };
 use prost::Message;
@@ -543,8 +543,8 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
     ) -> Result<Option<BlockFinality>, Error> {
         let block: Option<EthereumBlock> = self
             .chain_store
-            .ancestor_block(ptr, offset)?
-            .map(json::from_value)
+            .ancestor_block(ptr.clone(), offset)?
+            .map(|data| ancestor_from_json(data, &ptr, offset))
             .transpose()?;
         Ok(block.map(|block| {
             BlockFinality::NonFinal(EthereumBlockWithCalls {
@@ -574,6 +574,23 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
     }
 }
 
+/// Turn the JSON data that the store has for the ancestor at `offset` of
+/// the block `ptr` into an `EthereumBlock`
+pub(crate) fn ancestor_from_json(
+    data: json::Value,
+    ptr: &BlockPtr,
+    offset: BlockNumber,
+) -> Result<EthereumBlock, StoreError> {
+    json::from_value(data).map_err(|source| StoreError::Deserialization {
+        context: format!(
+            "the ancestor at offset {} of block {}",
+            offset,
+            ptr.hash_hex()
+        ),
+        source,
+    })
+}
+
 pub struct FirehoseMapper {}
 
 impl FirehoseMapperTrait<Chain> for FirehoseMapper {
@@ -3,13 +3,14 @@ use std::sync::Arc;
 use graph::{
     blockchain::{block_stream::BlockWithTriggers, BlockPtr},
     prelude::{
+        serde_json as json,
         web3::types::{Address, Bytes, Log, H160, H256, U64},
-        EthereumCall,
+        EthereumCall, StoreError,
     },
 };
 
 use crate::{
-    chain::BlockFinality,
+    chain::{ancestor_from_json, BlockFinality},
     trigger::{EthereumBlockTriggerType, EthereumTrigger},
 };
 
@@ -95,3 +96,18 @@ fn test_trigger_ordering() {
         vec![log1, log2, call1, log3, call2, call4, call3, block2, block1]
     );
 }
+
+#[test]
+fn malformed_ancestor_block() {
+    let ptr = BlockPtr::from((H256::repeat_byte(0xab), 7u64));
+    let data = json::json!({ "block": "not a block", "transaction_receipts": [] });
+
+    let err = ancestor_from_json(data, &ptr, 3).expect_err("malformed data can not be used");
+    match &err {
+        StoreError::Deserialization { .. } => (),
+        _ => panic!("expected a deserialization error but got {:?}", err),
+    }
+    let msg = err.to_string();
+    assert!(msg.contains(&ptr.hash_hex()), "`{}` names the block", msg);
+    assert!(msg.contains("offset 3"), "`{}` names the offset", msg);
+}
@@ -6,8 +6,7 @@ use crate::{
 };
 
 lazy_static! {
-    // graph_node::config disallows setting this in a store with multiple
-    // shards. See 8b6ad0c64e244023ac20ced7897fe666 for the reason
+    // Remove blocks that no subgraph needs anymore from the block cache
     pub static ref CLEANUP_BLOCKS: bool = std::env::var("GRAPH_ETHEREUM_CLEANUP_BLOCKS")
         .ok()
         .map(|s| s.eq_ignore_ascii_case("true"))
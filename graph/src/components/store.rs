@@ -792,6 +792,17 @@ pub enum StoreError {
     Canceled,
     #[error("database unavailable")]
     DatabaseUnavailable,
+    #[error("timed out waiting for a database connection")]
+    ConnectionTimeout,
+    #[error("database statement timed out")]
+    StatementTimeout,
+    /// Data that we read from the database could not be turned into the
+    /// type we expected, most likely because it is corrupt
+    #[error("failed to deserialize {context}: {source}")]
+    Deserialization {
+        context: String,
+        source: serde_json::Error,
+    },
 }
 
 // Convenience to report a constraint violation
@@ -1374,6 +1385,13 @@ pub trait EthereumCallCache: Send + Sync + 'static {
         block: BlockPtr,
     ) -> Result<Option<Vec<u8>>, Error>;
 
+    /// Cached return values for several calls at once. The result has one
+    /// entry for each request, in the same order as `requests`
+    fn get_calls(
+        &self,
+        requests: &[(ethabi::Address, Vec<u8>, BlockPtr)],
+    ) -> Result<Vec<Option<Vec<u8>>>, Error>;
+
     // Add entry to the cache.
     fn set_call(
         &self,
@@ -1382,6 +1400,23 @@ pub trait EthereumCallCache: Send + Sync + 'static {
         block: BlockPtr,
         return_value: &[u8],
     ) -> Result<(), Error>;
+
+    /// Add several entries to the cache in one transaction. Each entry is
+    /// a tuple of contract address, encoded call, block and return value
+    fn set_calls(
+        &self,
+        calls: &[(ethabi::Address, Vec<u8>, BlockPtr, Vec<u8>)],
+    ) -> Result<(), Error>;
+
+    /// Remove the cached return value for a single call, leaving other
+    /// calls for the same contract alone. Returns whether the call was
+    /// in the cache
+    fn remove_call(
+        &self,
+        contract_address: ethabi::Address,
+        encoded_call: &[u8],
+        block: BlockPtr,
+    ) -> Result<bool, Error>;
 }
 
 /// Store operations used when serving queries for a specific deployment
@@ -81,6 +81,8 @@ pub enum QueryExecutionError {
     SubgraphManifestResolveError(Arc<SubgraphManifestResolveError>),
     InvalidSubgraphManifest,
     ResultTooBig(usize, usize),
+    DuplicateResponseKey(String),
+    IntrospectionDisabled(Pos, String),
 }
 
 impl QueryExecutionError {
@@ -135,7 +137,9 @@ impl QueryExecutionError {
             | DeploymentReverted
             | SubgraphManifestResolveError(_)
             | InvalidSubgraphManifest
-            | ResultTooBig(_, _) => false,
+            | ResultTooBig(_, _)
+            | DuplicateResponseKey(_)
+            | IntrospectionDisabled(_, _) => false,
         }
     }
 }
@@ -279,6 +283,8 @@ impl fmt::Display for QueryExecutionError {
             SubgraphManifestResolveError(e) => write!(f, "failed to resolve subgraph manifest: {}", e),
             InvalidSubgraphManifest => write!(f, "invalid subgraph manifest file"),
             ResultTooBig(actual, limit) => write!(f, "the result size of {} is larger than the allowed limit of {}", actual, limit),
+            DuplicateResponseKey(key) => write!(f, "the response key `{}` appears in more than one partial result", key),
+            IntrospectionDisabled(_, field) => write!(f, "introspection is disabled, but the query selects `{}`", field),
         }
     }
 }
@@ -442,7 +448,8 @@ impl Serialize for QueryError {
             | QueryError::ExecutionError(AmbiguousDerivedFromResult(pos, _, _, _))
             | QueryError::ExecutionError(EnumCoercionError(pos, _, _, _, _))
             | QueryError::ExecutionError(ScalarCoercionError(pos, _, _, _))
-            | QueryError::ExecutionError(UnknownField(pos, _, _)) => {
+            | QueryError::ExecutionError(UnknownField(pos, _, _))
+            | QueryError::ExecutionError(IntrospectionDisabled(pos, _)) => {
                 let mut location = HashMap::new();
                 location.insert("line", pos.line);
                 location.insert("column", pos.column);
@@ -136,6 +136,9 @@ pub struct Query {
     pub shape_hash: u64,
     pub query_text: Arc<String>,
     pub variables_text: Arc<String>,
+    /// The name of the operation in `document` that should be executed;
+    /// only needed if the document contains more than one operation
+    pub operation_name: Option<String>,
     _force_use_of_new: (),
 }
 
@@ -160,7 +163,15 @@ impl Query {
             shape_hash,
             query_text: Arc::new(query_text),
             variables_text: Arc::new(variables_text),
+            operation_name: None,
             _force_use_of_new: (),
         }
     }
+
+    /// Select the operation with the given name from a document that
+    /// contains several operations
+    pub fn with_operation_name(mut self, operation_name: Option<String>) -> Self {
+        self.operation_name = operation_name;
+        self
+    }
 }
@@ -41,6 +41,75 @@ where
 
 pub type Data = BTreeMap<String, r::Value>;
 
+/// The extension that holds how long resolving each field of a query took
+const TRACING_EXTENSION: &str = "tracing";
+
+/// Add the extensions from `other` to `extensions`. The traces of both are
+/// combined into one with `merge_traces`; any other extension in `other`
+/// replaces the one with the same name in `extensions`
+fn merge_extensions(extensions: &mut Data, other: &Data) {
+    for (name, value) in other {
+        let value = match (name.as_str(), extensions.remove(name)) {
+            (TRACING_EXTENSION, Some(trace)) => merge_traces(&trace, value),
+            _ => value.clone(),
+        };
+        extensions.insert(name.clone(), value);
+    }
+}
+
+/// Combine the traces for two parts of the same query that were executed
+/// one after the other. The combined trace lasts as long as both traces
+/// together and lists the resolvers of `other` after those of `trace`,
+/// with their `startOffset` moved by the duration of `trace`. If either
+/// value is not a trace, `other` replaces `trace`
+fn merge_traces(trace: &r::Value, other: &r::Value) -> r::Value {
+    fn parts(trace: &r::Value) -> Option<(i64, &Vec<r::Value>)> {
+        let trace = match trace {
+            r::Value::Object(trace) => trace,
+            _ => return None,
+        };
+        let duration = match trace.get("duration") {
+            Some(r::Value::Int(duration)) => *duration,
+            _ => return None,
+        };
+        let resolvers = match trace.get("execution") {
+            Some(r::Value::Object(execution)) => match execution.get("resolvers") {
+                Some(r::Value::List(resolvers)) => resolvers,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some((duration, resolvers))
+    }
+
+    let ((duration, resolvers), (other_duration, other_resolvers)) =
+        match (parts(trace), parts(other)) {
+            (Some(parts), Some(other_parts)) => (parts, other_parts),
+            _ => return other.clone(),
+        };
+
+    let mut combined = resolvers.clone();
+    combined.extend(other_resolvers.iter().map(|resolver| {
+        let mut resolver = resolver.clone();
+        if let r::Value::Object(fields) = &mut resolver {
+            if let Some(r::Value::Int(offset)) = fields.get_mut("startOffset") {
+                *offset += duration;
+            }
+        }
+        resolver
+    }));
+
+    let mut execution = BTreeMap::new();
+    execution.insert("resolvers".to_owned(), r::Value::List(combined));
+    let mut trace = BTreeMap::new();
+    trace.insert(
+        "duration".to_owned(),
+        r::Value::Int(duration + other_duration),
+    );
+    trace.insert("execution".to_owned(), r::Value::Object(execution));
+    r::Value::Object(trace)
+}
+
 #[derive(Debug)]
 /// A collection of query results that is serialized as a single result.
 pub struct QueryResults {
@@ -70,6 +139,10 @@ impl Serialize for QueryResults {
         if has_errors {
             len += 1;
         }
+        let has_extensions = self.results.iter().any(|r| r.extensions.is_some());
+        if has_extensions {
+            len += 1;
+        }
 
         let mut state = serializer.serialize_struct("QueryResults", len)?;
 
@@ -106,6 +179,25 @@ impl Serialize for QueryResults {
             state.serialize_field("errors", &SerError(self))?;
         }
 
+        // Serialize extensions.
+        if has_extensions {
+            struct SerExtensions<'a>(&'a QueryResults);
+
+            impl Serialize for SerExtensions<'_> {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    // Each result can have its own trace, and we must only
+                    // report one
+                    let mut extensions = Data::new();
+                    for other in self.0.results.iter().filter_map(|r| r.extensions.as_ref()) {
+                        merge_extensions(&mut extensions, other);
+                    }
+                    serialize_value_map(std::iter::once(&extensions), serializer)
+                }
+            }
+
+            state.serialize_field("extensions", &SerExtensions(self))?;
+        }
+
         state.end()
     }
 }
@@ -182,6 +274,14 @@ pub struct QueryResult {
     data: Option<Data>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     errors: Vec<QueryError>,
+    /// Additional information about the execution of the query that is
+    /// not part of the data, like tracing data, keyed by the name of the
+    /// extension
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_data"
+    )]
+    extensions: Option<Data>,
     #[serde(skip_serializing)]
     pub deployment: Option<DeploymentHash>,
 }
@@ -191,6 +291,7 @@ impl QueryResult {
         QueryResult {
             data: Some(data),
             errors: Vec::new(),
+            extensions: None,
             deployment: None,
         }
     }
@@ -203,6 +304,7 @@ impl QueryResult {
         Self {
             data: self.data.clone(),
             errors: self.errors.clone(),
+            extensions: self.extensions.clone(),
             deployment: self.deployment.clone(),
         }
     }
@@ -238,6 +340,48 @@ impl QueryResult {
     pub fn errors_mut(&mut self) -> &mut Vec<QueryError> {
         &mut self.errors
     }
+
+    pub fn extensions(&self) -> Option<&Data> {
+        self.extensions.as_ref()
+    }
+
+    /// Set the extension `name` to `value`, replacing any previous value
+    pub fn set_extension(&mut self, name: &str, value: r::Value) {
+        self.extensions
+            .get_or_insert_with(Data::new)
+            .insert(name.to_owned(), value);
+    }
+
+    /// Combine `other`, the result of executing another part of the same
+    /// query, with this result. The data of both results is combined into
+    /// one map and the errors of `other` are added after ours. If both
+    /// results have data for the same response key, nothing is changed
+    /// and an error is returned
+    pub fn merge(&mut self, other: QueryResult) -> Result<(), QueryExecutionError> {
+        if let (Some(data), Some(other_data)) = (&self.data, &other.data) {
+            if let Some(key) = other_data.keys().find(|key| data.contains_key(*key)) {
+                return Err(QueryExecutionError::DuplicateResponseKey(key.to_owned()));
+            }
+        }
+
+        let QueryResult {
+            data,
+            errors,
+            extensions,
+            deployment,
+        } = other;
+        if let Some(other_data) = data {
+            self.data.get_or_insert_with(Data::new).extend(other_data);
+        }
+        self.errors.extend(errors);
+        if let Some(extensions) = extensions {
+            merge_extensions(self.extensions.get_or_insert_with(Data::new), &extensions);
+        }
+        if self.deployment.is_none() {
+            self.deployment = deployment;
+        }
+        Ok(())
+    }
 }
 
 impl From<QueryExecutionError> for QueryResult {
@@ -245,6 +389,7 @@ impl From<QueryExecutionError> for QueryResult {
         QueryResult {
             data: None,
             errors: vec![e.into()],
+            extensions: None,
             deployment: None,
         }
     }
@@ -255,6 +400,7 @@ impl From<QueryError> for QueryResult {
         QueryResult {
             data: None,
             errors: vec![e],
+            extensions: None,
             deployment: None,
         }
     }
@@ -265,6 +411,7 @@ impl From<Vec<QueryExecutionError>> for QueryResult {
         QueryResult {
             data: None,
             errors: e.into_iter().map(QueryError::from).collect(),
+            extensions: None,
             deployment: None,
         }
     }
@@ -276,6 +423,19 @@ impl From<Data> for QueryResult {
     }
 }
 
+/// Data together with the errors for the fields in it that were set to
+/// `null` because they could not be resolved
+impl From<(Data, Vec<QueryExecutionError>)> for QueryResult {
+    fn from((data, errors): (Data, Vec<QueryExecutionError>)) -> Self {
+        QueryResult {
+            data: Some(data),
+            errors: errors.into_iter().map(QueryError::from).collect(),
+            extensions: None,
+            deployment: None,
+        }
+    }
+}
+
 impl TryFrom<r::Value> for QueryResult {
     type Error = &'static str;
 
@@ -298,7 +458,9 @@ impl<V: Into<QueryResult>, E: Into<QueryResult>> From<Result<V, E>> for QueryRes
 
 impl CacheWeight for QueryResult {
     fn indirect_weight(&self) -> usize {
-        self.data.indirect_weight() + self.errors.indirect_weight()
+        self.data.indirect_weight()
+            + self.errors.indirect_weight()
+            + self.extensions.indirect_weight()
     }
 }
 
@@ -326,3 +488,113 @@ fn multiple_data_items() {
     let actual = serde_json::to_string(&res).unwrap();
     assert_eq!(expected, actual)
 }
+
+#[test]
+fn extensions() {
+    use serde_json::json;
+
+    let mut map = BTreeMap::new();
+    map.insert("key".to_owned(), r::Value::String("value".to_owned()));
+    let mut result = QueryResult::new(map);
+    let actual = serde_json::to_string(&result).unwrap();
+    assert_eq!(r#"{"data":{"key":"value"}}"#, actual);
+
+    result.set_extension("tracing", r::Value::Int(1));
+    let mut res = QueryResults::empty();
+    res.append(Arc::new(result));
+
+    let expected =
+        serde_json::to_string(&json!({"data":{"key": "value"}, "extensions": {"tracing": 1}}))
+            .unwrap();
+    let actual = serde_json::to_string(&res).unwrap();
+    assert_eq!(expected, actual)
+}
+
+#[test]
+fn merged_traces() {
+    use serde_json::json;
+
+    fn make_result(key: &str, field: &str, duration: i64) -> Arc<QueryResult> {
+        let mut resolver = BTreeMap::new();
+        resolver.insert("fieldName".to_owned(), r::Value::String(field.to_owned()));
+        resolver.insert("startOffset".to_owned(), r::Value::Int(10));
+        resolver.insert("duration".to_owned(), r::Value::Int(20));
+        let mut execution = BTreeMap::new();
+        execution.insert(
+            "resolvers".to_owned(),
+            r::Value::List(vec![r::Value::Object(resolver)]),
+        );
+        let mut trace = BTreeMap::new();
+        trace.insert("duration".to_owned(), r::Value::Int(duration));
+        trace.insert("execution".to_owned(), r::Value::Object(execution));
+
+        let mut map = BTreeMap::new();
+        map.insert(key.to_owned(), r::Value::Int(1));
+        let mut result = QueryResult::new(map);
+        result.set_extension("tracing", r::Value::Object(trace));
+        Arc::new(result)
+    }
+
+    let mut res = QueryResults::empty();
+    res.append(make_result("a", "first", 100));
+    res.append(make_result("b", "second", 50));
+
+    let expected = json!({
+        "data": {"a": 1, "b": 1},
+        "extensions": {
+            "tracing": {
+                "duration": 150,
+                "execution": {
+                    "resolvers": [
+                        {"fieldName": "first", "startOffset": 10, "duration": 20},
+                        {"fieldName": "second", "startOffset": 110, "duration": 20}
+                    ]
+                }
+            }
+        }
+    });
+    let actual = serde_json::to_string(&res).unwrap();
+    assert_eq!(serde_json::to_string(&expected).unwrap(), actual);
+    // The serialized response must only contain one trace
+    assert_eq!(1, actual.matches("\"tracing\"").count());
+}
+
+#[test]
+fn merge() {
+    use serde_json::json;
+
+    fn make_obj(key: &str, value: &str) -> QueryResult {
+        let mut map = BTreeMap::new();
+        map.insert(key.to_owned(), r::Value::String(value.to_owned()));
+        map.into()
+    }
+
+    // Disjoint keys are combined, and errors are concatenated
+    let mut result = make_obj("key1", "value1");
+    let mut other = make_obj("key2", "value2");
+    other
+        .errors_mut()
+        .push(QueryExecutionError::TooDeep(3).into());
+    result.merge(other).unwrap();
+    result.merge(QueryExecutionError::Timeout.into()).unwrap();
+
+    let expected = json!({
+        "data": {"key1": "value1", "key2": "value2"},
+        "errors": [
+            {"message": "query has a depth that exceeds the limit of `3`"},
+            {"message": "Query timed out"}
+        ]
+    });
+    assert_eq!(expected, serde_json::to_value(&result).unwrap());
+
+    // A key that is in both results is an error and leaves the result alone
+    let mut result = make_obj("key1", "value1");
+    let mut other = make_obj("key1", "value2");
+    other.errors_mut().push(QueryExecutionError::Timeout.into());
+    match result.merge(other) {
+        Err(QueryExecutionError::DuplicateResponseKey(key)) => assert_eq!("key1", key),
+        res => panic!("expected a duplicate key error but got {:?}", res),
+    }
+    let expected = json!({"data": {"key1": "value1"}});
+    assert_eq!(expected, serde_json::to_value(&result).unwrap());
+}
@@ -352,6 +352,7 @@ pub struct ApiSchema {
     // Root types for the api schema.
     pub query_type: Arc<ObjectType>,
     pub subscription_type: Option<Arc<ObjectType>>,
+    pub mutation_type: Option<Arc<ObjectType>>,
 }
 
 impl ApiSchema {
@@ -367,11 +368,17 @@ impl ApiSchema {
             .get_root_subscription_type()
             .cloned()
             .map(Arc::new);
+        let mutation_type = api_schema
+            .document
+            .get_root_mutation_type()
+            .cloned()
+            .map(Arc::new);
 
         Ok(Self {
             schema: api_schema,
             query_type: Arc::new(query_type),
             subscription_type,
+            mutation_type,
         })
     }
 
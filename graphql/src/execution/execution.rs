@@ -7,12 +7,15 @@ use graph::{
 };
 use indexmap::IndexMap;
 use lazy_static::lazy_static;
+use rayon::prelude::{ParallelIterator, ParallelSliceMut};
 use stable_hash::crypto::SetHasher;
 use stable_hash::prelude::*;
 use stable_hash::utils::stable_hash;
+use std::any::Any;
 use std::borrow::ToOwned;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::iter;
+use std::panic::{self, AssertUnwindSafe};
 use std::time::Instant;
 
 use graph::data::graphql::*;
@@ -26,6 +29,7 @@ use crate::introspection::{
 };
 use crate::prelude::*;
 use crate::query::ast as qast;
+use crate::runner::GRAPHQL_LIST_CONCURRENCY;
 use crate::schema::ast as sast;
 use crate::values::coercion;
 
@@ -92,6 +96,15 @@ lazy_static! {
 
 
 
+    /// The threads on which the elements of lists are completed when list
+    /// concurrency is turned on. All queries share them so that there are
+    /// never more than `GRAPH_GRAPHQL_LIST_CONCURRENCY` of them
+    static ref LIST_POOL: rayon::ThreadPool = rayon::ThreadPoolBuilder::new()
+        .num_threads((*GRAPHQL_LIST_CONCURRENCY).max(1))
+        .thread_name(|i| format!("graphql-list-{}", i))
+        .build()
+        .expect("failed to start the threads for completing lists");
+
     // Sharded query results cache for recent blocks by network.
     // The `VecDeque` works as a ring buffer with a capacity of `QUERY_CACHE_BLOCKS`.
     static ref QUERY_BLOCK_CACHE: Vec<TimedMutex<QueryBlockCache>> = {
@@ -228,8 +241,19 @@ where
     /// Max value for `skip`
     pub max_skip: u32,
 
+    /// How many elements of a list to complete at the same time. With 1,
+    /// elements are completed one after the other
+    pub list_concurrency: usize,
+
     /// Records whether this was a cache hit, used for logging.
     pub(crate) cache_status: AtomicCell<CacheStatus>,
+
+    /// Collects how long resolving each field took if tracing was
+    /// requested for the query.
+    pub(crate) trace: Option<Trace>,
+
+    /// The coercions to use for scalar arguments.
+    pub scalar_coercions: Arc<ScalarCoercions>,
 }
 
 // Helpers to look for types and fields on both the introspection and regular schemas.
@@ -279,18 +303,25 @@ where
             deadline: self.deadline,
             max_first: std::u32::MAX,
             max_skip: std::u32::MAX,
+            list_concurrency: 1,
 
             // `cache_status` is a dead value for the introspection context.
             cache_status: AtomicCell::new(CacheStatus::Miss),
+            trace: None,
+            scalar_coercions: self.scalar_coercions.cheap_clone(),
         }
     }
 }
 
+/// Executes the root selection set of a query without consulting the
+/// cache. Returns the data together with the errors for fields that were
+/// resolved to `null` because of an error. If errors make it impossible to
+/// return any data, only the errors are returned
 pub fn execute_root_selection_set_uncached(
     ctx: &ExecutionContext<impl Resolver>,
     selection_set: &q::SelectionSet,
     root_type: &s::ObjectType,
-) -> Result<BTreeMap<String, r::Value>, Vec<QueryExecutionError>> {
+) -> Result<(BTreeMap<String, r::Value>, Vec<QueryExecutionError>), Vec<QueryExecutionError>> {
     // Split the top-level fields into introspection fields and
     // regular data fields
     let mut data_set = q::SelectionSet {
@@ -318,28 +349,51 @@ pub fn execute_root_selection_set_uncached(
         }
     }
 
+    let mut errors = Vec::new();
+
     // If we are getting regular data, prefetch it from the database
     let mut values = if data_set.items.is_empty() && meta_items.is_empty() {
         BTreeMap::default()
     } else {
         let initial_data = ctx.resolver.prefetch(&ctx, &data_set)?;
         data_set.items.extend(meta_items);
-        execute_selection_set_to_map(&ctx, iter::once(&data_set), root_type, initial_data)?
+        let values = execute_selection_set_to_map(
+            &ctx,
+            iter::once(&data_set),
+            root_type,
+            initial_data,
+            &mut errors,
+        );
+        match values {
+            Ok(values) => values,
+            Err(mut e) => {
+                errors.append(&mut e);
+                return Err(errors);
+            }
+        }
     };
 
     // Resolve introspection fields, if there are any
     if !intro_set.items.is_empty() {
         let ictx = ctx.as_introspection_context();
 
-        values.extend(execute_selection_set_to_map(
+        let intro_values = execute_selection_set_to_map(
             &ictx,
             iter::once(&intro_set),
             &*INTROSPECTION_QUERY_TYPE,
             None,
-        )?);
+            &mut errors,
+        );
+        match intro_values {
+            Ok(intro_values) => values.extend(intro_values),
+            Err(mut e) => {
+                errors.append(&mut e);
+                return Err(errors);
+            }
+        }
     }
 
-    Ok(values)
+    Ok((values, errors))
 }
 
 /// Executes the root selection set of a query.
@@ -353,7 +407,12 @@ pub async fn execute_root_selection_set<R: Resolver>(
     // and once for insert.
     let mut key: Option<QueryHash> = None;
 
-    if R::CACHEABLE && (*CACHE_ALL || CACHED_SUBGRAPH_IDS.contains(ctx.query.schema.id())) {
+    // Traced results contain timings for this particular execution, and
+    // can therefore neither come from nor go into the cache
+    if R::CACHEABLE
+        && ctx.trace.is_none()
+        && (*CACHE_ALL || CACHED_SUBGRAPH_IDS.contains(ctx.query.schema.id()))
+    {
         if let (Some(block_ptr), Some(network)) = (block_ptr.as_ref(), &ctx.query.network) {
             // JSONB and metadata queries use `BLOCK_NUMBER_MAX`. Ignore this case for two reasons:
             // - Metadata queries are not cacheable.
@@ -403,6 +462,9 @@ pub async fn execute_root_selection_set<R: Resolver>(
 
             // Unwrap: In practice should never fail, but if it does we will catch the panic.
             execute_ctx.resolver.post_process(&mut query_res).unwrap();
+            if let Some(trace) = &execute_ctx.trace {
+                query_res.set_extension("tracing", trace.to_value());
+            }
             query_res.deployment = Some(execute_ctx.query.schema.id().clone());
             Arc::new(query_res)
         })
@@ -410,15 +472,7 @@ pub async fn execute_root_selection_set<R: Resolver>(
         {
             Ok(result) => result,
             Err(e) => {
-                let e = e.into_panic();
-                let e = match e
-                    .downcast_ref::<String>()
-                    .map(String::as_str)
-                    .or(e.downcast_ref::<&'static str>().copied())
-                {
-                    Some(e) => e.to_string(),
-                    None => "panic is not a string".to_string(),
-                };
+                let e = panic_message(&*e.into_panic());
                 error!(
                     logger,
                     "panic when processing graphql query";
@@ -484,6 +538,75 @@ pub async fn execute_root_selection_set<R: Resolver>(
     result
 }
 
+/// Executes the selection set of a mutation. Unlike the fields of a query,
+/// the fields of a mutation are executed serially, in the order in which
+/// they appear in the selection set. Like `execute_root_selection_set_uncached`,
+/// returns the data together with the errors for fields that were resolved
+/// to `null`
+pub fn execute_mutation_selection_set(
+    ctx: &ExecutionContext<impl Resolver>,
+    selection_set: &q::SelectionSet,
+    mutation_type: &s::ObjectType,
+) -> Result<(BTreeMap<String, r::Value>, Vec<QueryExecutionError>), Vec<QueryExecutionError>> {
+    let mut errors: Vec<QueryExecutionError> = Vec::new();
+    let mut field_errors: Vec<QueryExecutionError> = Vec::new();
+    let mut result_map: BTreeMap<String, r::Value> = BTreeMap::new();
+
+    for (response_key, fields) in collect_fields(ctx, mutation_type, iter::once(selection_set)) {
+        match ctx.deadline {
+            Some(deadline) if deadline < Instant::now() => {
+                errors.push(QueryExecutionError::Timeout);
+                break;
+            }
+            _ => (),
+        }
+
+        // Unwrap: The query was validated to contain only valid fields.
+        let field = sast::get_field(mutation_type, &fields[0].name).unwrap();
+
+        let value =
+            coerce_argument_values(&ctx.query, mutation_type, &fields[0], &ctx.scalar_coercions)
+                .and_then(|argument_values| {
+                    ctx.resolver
+                        .resolve_mutation(&fields[0], field, &argument_values)
+                        .map_err(|e| vec![e])
+                })
+                .and_then(|value| {
+                    complete_value(
+                        ctx,
+                        &fields[0],
+                        &field.field_type,
+                        &fields,
+                        value,
+                        &mut field_errors,
+                    )
+                });
+        match value {
+            Ok(v) => {
+                result_map.insert(response_key.to_owned(), v);
+            }
+            Err(mut e) if !sast::is_non_null_type(&field.field_type) && !has_timeout(&e) => {
+                field_errors.append(&mut e);
+                result_map.insert(response_key.to_owned(), r::Value::Null);
+            }
+            Err(mut e) => {
+                let timeout = has_timeout(&e);
+                errors.append(&mut e);
+                if timeout {
+                    break;
+                }
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok((result_map, field_errors))
+    } else {
+        field_errors.append(&mut errors);
+        Err(field_errors)
+    }
+}
+
 /// Executes a selection set, requiring the result to be of the given object type.
 ///
 /// Allows passing in a parent value during recursive processing of objects and their fields.
@@ -492,20 +615,39 @@ fn execute_selection_set<'a>(
     selection_sets: impl Iterator<Item = &'a q::SelectionSet>,
     object_type: &s::ObjectType,
     prefetched_value: Option<r::Value>,
+    field_errors: &mut Vec<QueryExecutionError>,
 ) -> Result<r::Value, Vec<QueryExecutionError>> {
     Ok(r::Value::Object(execute_selection_set_to_map(
         ctx,
         selection_sets,
         object_type,
         prefetched_value,
+        field_errors,
     )?))
 }
 
+/// Whether `errors` contain a timeout. A timeout is never turned into a
+/// `null` like other field errors: execution has to stop at the first one
+/// since otherwise every field that has not been resolved yet would report
+/// its own timeout
+fn has_timeout(errors: &[QueryExecutionError]) -> bool {
+    errors
+        .iter()
+        .any(|e| matches!(e, QueryExecutionError::Timeout))
+}
+
+/// Executes a selection set and returns the values of its fields. A field
+/// that fails to resolve is set to `null` if its type is nullable, and
+/// the errors that caused that are added to `field_errors`. If a non-null
+/// field fails, the whole object can not be resolved and we return an
+/// error so that the `null` propagates to the closest nullable parent.
+/// A timeout stops execution and is returned once
 fn execute_selection_set_to_map<'a>(
     ctx: &'a ExecutionContext<impl Resolver>,
     selection_sets: impl Iterator<Item = &'a q::SelectionSet>,
     object_type: &s::ObjectType,
     prefetched_value: Option<r::Value>,
+    field_errors: &mut Vec<QueryExecutionError>,
 ) -> Result<BTreeMap<String, r::Value>, Vec<QueryExecutionError>> {
     let mut prefetched_object = match prefetched_value {
         Some(r::Value::Object(object)) => Some(object),
@@ -560,12 +702,29 @@ fn execute_selection_set_to_map<'a>(
                 }
             })
             .flatten();
-        match execute_field(&ctx, object_type, field_value, &fields[0], field, fields) {
+        let nullable = !sast::is_non_null_type(&field.field_type);
+        match execute_field(
+            &ctx,
+            object_type,
+            field_value,
+            &fields[0],
+            field,
+            fields,
+            field_errors,
+        ) {
             Ok(v) => {
                 result_map.insert(response_key.to_owned(), v);
             }
+            Err(mut e) if nullable && !has_timeout(&e) => {
+                field_errors.append(&mut e);
+                result_map.insert(response_key.to_owned(), r::Value::Null);
+            }
             Err(mut e) => {
+                let timeout = has_timeout(&e);
                 errors.append(&mut e);
+                if timeout {
+                    break;
+                }
             }
         }
     }
@@ -701,8 +860,11 @@ fn execute_field(
     field: &q::Field,
     field_definition: &s::Field,
     fields: Vec<&q::Field>,
+    field_errors: &mut Vec<QueryExecutionError>,
 ) -> Result<r::Value, Vec<QueryExecutionError>> {
-    coerce_argument_values(&ctx.query, object_type, field)
+    let start = ctx.trace.as_ref().map(|_| Instant::now());
+
+    let value = coerce_argument_values(&ctx.query, object_type, field, &ctx.scalar_coercions)
         .and_then(|argument_values| {
             resolve_field_value(
                 ctx,
@@ -714,7 +876,21 @@ fn execute_field(
                 &argument_values,
             )
         })
-        .and_then(|value| complete_value(ctx, field, &field_definition.field_type, &fields, value))
+        .and_then(|value| {
+            complete_value(
+                ctx,
+                field,
+                &field_definition.field_type,
+                &fields,
+                value,
+                field_errors,
+            )
+        });
+
+    if let (Some(trace), Some(start)) = (&ctx.trace, start) {
+        trace.record(&object_type.name, &field.name, start);
+    }
+    value
 }
 
 /// Resolves the value of a field.
@@ -792,10 +968,22 @@ fn resolve_field_value_for_named_type(
         s::TypeDefinition::Enum(t) => ctx.resolver.resolve_enum_value(field, t, field_value),
 
         // Let the resolver decide how values in the resolved object value
-        // map to values of GraphQL scalars
+        // map to values of GraphQL scalars, unless the field counts the
+        // entries of a list
         s::TypeDefinition::Scalar(t) => {
-            ctx.resolver
-                .resolve_scalar_value(object_type, field, t, field_value, argument_values)
+            match counted_list_field(ctx, object_type, field, t, &field_value) {
+                Some(list_field) => {
+                    ctx.resolver
+                        .resolve_entity_count(object_type, list_field, argument_values)
+                }
+                None => ctx.resolver.resolve_scalar_value(
+                    object_type,
+                    field,
+                    t,
+                    field_value,
+                    argument_values,
+                ),
+            }
         }
 
         s::TypeDefinition::Interface(i) => ctx.resolver.resolve_object(
@@ -806,13 +994,34 @@ fn resolve_field_value_for_named_type(
             argument_values,
         ),
 
-        s::TypeDefinition::Union(_) => Err(QueryExecutionError::Unimplemented("unions".to_owned())),
+        // Let the resolver produce an object value; its concrete type is
+        // determined when the value is completed
+        s::TypeDefinition::Union(t) => ctx.resolver.resolve_union_value(field, t, field_value),
 
         s::TypeDefinition::InputObject(_) => unreachable!("input objects are never resolved"),
     }
     .map_err(|e| vec![e])
 }
 
+/// Return the list field of `object_type` that `field` counts according to
+/// the resolver's `count_field_suffix`. Only `Int` fields for which the
+/// parent object does not already have a value count anything
+fn counted_list_field<'a>(
+    ctx: &ExecutionContext<impl Resolver>,
+    object_type: &'a s::ObjectType,
+    field: &q::Field,
+    scalar_type: &s::ScalarType,
+    field_value: &Option<r::Value>,
+) -> Option<&'a s::Field> {
+    let suffix = ctx.resolver.count_field_suffix()?;
+    if scalar_type.name != "Int" || field_value.is_some() {
+        return None;
+    }
+    let list_name = field.name.strip_suffix(suffix)?;
+    sast::get_field(object_type, list_name)
+        .filter(|list_field| sast::is_list_or_non_null_list_field(list_field))
+}
+
 /// Resolves the value of a field that corresponds to a list type.
 fn resolve_field_value_for_list_type(
     ctx: &ExecutionContext<impl Resolver>,
@@ -879,9 +1088,11 @@ fn resolve_field_value_for_list_type(
                     )
                     .map_err(|e| vec![e]),
 
-                s::TypeDefinition::Union(_) => Err(vec![QueryExecutionError::Unimplemented(
-                    "unions".to_owned(),
-                )]),
+                // Each element of the list is completed with its own
+                // concrete type
+                s::TypeDefinition::Union(t) => {
+                    ctx.resolver.resolve_union_values(field, &t, field_value)
+                }
 
                 s::TypeDefinition::InputObject(_) => {
                     unreachable!("input objects are never resolved")
@@ -889,25 +1100,139 @@ fn resolve_field_value_for_list_type(
             }
         }
 
-        // We don't support nested lists yet
-        s::Type::ListType(_) => Err(vec![QueryExecutionError::Unimplemented(
-            "nested list types".to_owned(),
-        )]),
+        // Resolvers only know how to produce one level of list; values for
+        // nested lists must have been provided by the parent, and
+        // `complete_value` checks them level by level
+        s::Type::ListType(_) => Ok(field_value.unwrap_or(r::Value::Null)),
+    }
+}
+
+/// Turn the payload of a panic into a message that we can report
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or(payload.downcast_ref::<&'static str>().copied())
+    {
+        Some(e) => e.to_string(),
+        None => "panic is not a string".to_string(),
+    }
+}
+
+/// Complete the elements of a list in place. Elements that fail are set to
+/// `null` if the list allows that, and their errors are added to
+/// `field_errors`. The errors for elements that can not be `null` are
+/// returned, and so is a timeout, after which no further elements are
+/// completed.
+///
+/// If the context allows it, the list is split into contiguous chunks that
+/// are completed on the threads of `LIST_POOL`. Combining the results chunk
+/// by chunk produces the same values and errors, in the same order, as
+/// completing the elements one after the other. A panic while completing a
+/// chunk is returned as a `QueryExecutionError::Panic`.
+fn complete_list_values(
+    ctx: &ExecutionContext<impl Resolver>,
+    field: &q::Field,
+    inner_type: &s::Type,
+    fields: &Vec<&q::Field>,
+    values: &mut [r::Value],
+    field_errors: &mut Vec<QueryExecutionError>,
+) -> Vec<QueryExecutionError> {
+    let concurrency = ctx.list_concurrency.min(values.len());
+    // Lists inside of elements that are already being completed on the
+    // pool are completed one element after the other
+    if concurrency <= 1 || LIST_POOL.current_thread_index().is_some() {
+        let mut errors = Vec::new();
+
+        // To avoid allocating a new vector this completes the values in place.
+        for value_place in values {
+            // Put in a placeholder, complete the value, put the completed value back.
+            // A list element that can be null is set to null
+            // if it fails; the placeholder already is null
+            let value = std::mem::replace(value_place, r::Value::Null);
+            match complete_value(ctx, field, inner_type, fields, value, field_errors) {
+                Ok(value) => {
+                    *value_place = value;
+                }
+                Err(errs) if !sast::is_non_null_type(inner_type) && !has_timeout(&errs) => {
+                    field_errors.extend(errs)
+                }
+                Err(errs) => {
+                    let timeout = has_timeout(&errs);
+                    errors.extend(errs);
+                    if timeout {
+                        break;
+                    }
+                }
+            }
+        }
+        return errors;
+    }
+
+    let chunk_size = (values.len() + concurrency - 1) / concurrency;
+    let results: Vec<_> = LIST_POOL.install(|| {
+        values
+            .par_chunks_mut(chunk_size)
+            .map(|chunk| {
+                panic::catch_unwind(AssertUnwindSafe(|| {
+                    let mut field_errors = Vec::new();
+                    let errors = complete_list_values(
+                        ctx,
+                        field,
+                        inner_type,
+                        fields,
+                        chunk,
+                        &mut field_errors,
+                    );
+                    (errors, field_errors)
+                }))
+            })
+            .collect()
+    });
+
+    let mut errors = Vec::new();
+    for result in results {
+        let (chunk_errors, chunk_field_errors) = match result {
+            Ok(result) => result,
+            Err(payload) => {
+                errors.push(QueryExecutionError::Panic(panic_message(&*payload)));
+                break;
+            }
+        };
+        // Every chunk runs into the deadline on its own; only report the
+        // first timeout
+        let timeout = has_timeout(&chunk_errors);
+        errors.extend(chunk_errors);
+        field_errors.extend(chunk_field_errors);
+        if timeout {
+            break;
+        }
     }
+    errors
 }
 
-/// Ensures that a value matches the expected return type.
+/// Ensures that a value matches the expected return type. Errors for
+/// nested values that could be replaced with `null` are added to
+/// `field_errors`
 fn complete_value(
     ctx: &ExecutionContext<impl Resolver>,
     field: &q::Field,
     field_type: &s::Type,
     fields: &Vec<&q::Field>,
     resolved_value: r::Value,
+    field_errors: &mut Vec<QueryExecutionError>,
 ) -> Result<r::Value, Vec<QueryExecutionError>> {
     match field_type {
         // Fail if the field type is non-null but the value is null
         s::Type::NonNullType(inner_type) => {
-            return match complete_value(ctx, field, inner_type, fields, resolved_value)? {
+            return match complete_value(
+                ctx,
+                field,
+                inner_type,
+                fields,
+                resolved_value,
+                field_errors,
+            )? {
                 r::Value::Null => Err(vec![QueryExecutionError::NonNullError(
                     field.position,
                     field.name.to_string(),
@@ -925,19 +1250,14 @@ fn complete_value(
             match resolved_value {
                 // Complete list values individually
                 r::Value::List(mut values) => {
-                    let mut errors = Vec::new();
-
-                    // To avoid allocating a new vector this completes the values in place.
-                    for value_place in &mut values {
-                        // Put in a placeholder, complete the value, put the completed value back.
-                        let value = std::mem::replace(value_place, r::Value::Null);
-                        match complete_value(ctx, field, inner_type, fields, value) {
-                            Ok(value) => {
-                                *value_place = value;
-                            }
-                            Err(errs) => errors.extend(errs),
-                        }
-                    }
+                    let errors = complete_list_values(
+                        ctx,
+                        field,
+                        inner_type,
+                        fields,
+                        &mut values,
+                        field_errors,
+                    );
                     match errors.is_empty() {
                         true => Ok(r::Value::List(values)),
                         false => Err(errors),
@@ -991,6 +1311,7 @@ fn complete_value(
                     fields.iter().map(|f| &f.selection_set),
                     object_type,
                     Some(resolved_value),
+                    field_errors,
                 ),
 
                 // Resolve interface types using the resolved value and complete the value recursively
@@ -1002,6 +1323,7 @@ fn complete_value(
                         fields.iter().map(|f| &f.selection_set),
                         object_type,
                         Some(resolved_value),
+                        field_errors,
                     )
                 }
 
@@ -1014,6 +1336,7 @@ fn complete_value(
                         fields.iter().map(|f| &f.selection_set),
                         object_type,
                         Some(resolved_value),
+                        field_errors,
                     )
                 }
 
@@ -1047,6 +1370,7 @@ pub fn coerce_argument_values<'a>(
     query: &crate::execution::Query,
     ty: impl Into<ObjectOrInterface<'a>>,
     field: &q::Field,
+    scalars: &ScalarCoercions,
 ) -> Result<HashMap<&'a str, r::Value>, Vec<QueryExecutionError>> {
     let mut coerced_values = HashMap::new();
     let mut errors = vec![];
@@ -1058,7 +1382,13 @@ pub fn coerce_argument_values<'a>(
         .flatten()
     {
         let value = qast::get_argument_value(&field.arguments, &argument_def.name).cloned();
-        match coercion::coerce_input_value(value, &argument_def, &resolver, &query.variables) {
+        match coercion::coerce_input_value(
+            value,
+            &argument_def,
+            &resolver,
+            &query.variables,
+            scalars,
+        ) {
             Ok(Some(value)) => {
                 if argument_def.name == "text".to_string() {
                     coerced_values.insert(
@@ -4,11 +4,14 @@ mod execution;
 mod query;
 /// Common trait for field resolvers used in the execution.
 mod resolver;
+/// Timing of field resolution when tracing is turned on.
+mod trace;
 
 use stable_hash::{crypto::SetHasher, StableHasher};
 
 pub use self::execution::*;
 pub use self::query::Query;
 pub use self::resolver::Resolver;
+pub(crate) use self::trace::Trace;
 
 type QueryHash = <SetHasher as StableHasher>::Out;
@@ -1,19 +1,21 @@
 use graphql_parser::Pos;
 use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use std::{collections::hash_map::DefaultHasher, convert::TryFrom};
 
 use graph::data::graphql::{
-    ext::{DocumentExt, TypeExt},
+    ext::{DirectiveExt, DirectiveFinder, DocumentExt, TypeExt, ValueExt},
     ObjectOrInterface,
 };
 use graph::data::query::{Query as GraphDataQuery, QueryVariables};
 use graph::data::schema::ApiSchema;
 use graph::prelude::{
-    info, o, q, r, s, BlockNumber, CheapClone, Logger, QueryExecutionError, TryFromValue,
+    info, o, q, r, s, warn, BlockNumber, CheapClone, DeploymentHash, Logger, QueryExecutionError,
+    TryFromValue,
 };
+use lazy_static::lazy_static;
 
 use crate::introspection::introspection_schema;
 use crate::query::{ast as qast, ext::BlockConstraint};
@@ -21,8 +23,17 @@ use crate::schema::ast as sast;
 use crate::{
     execution::{get_field, get_named_type, object_or_interface},
     schema::api::ErrorPolicy,
+    values::ScalarCoercions,
 };
 
+lazy_static! {
+    /// The deprecated fields that queries have used so far, identified by
+    /// deployment, type and field name. We only warn about the first use of
+    /// each of them since a busy query would otherwise flood the logs
+    static ref DEPRECATION_WARNINGS: Mutex<HashSet<(DeploymentHash, String, String)>> =
+        Mutex::new(HashSet::new());
+}
+
 #[derive(Clone, Debug)]
 pub enum ComplexityError {
     TooDeep,
@@ -35,6 +46,7 @@ pub enum ComplexityError {
 enum Kind {
     Query,
     Subscription,
+    Mutation,
 }
 
 /// Helper to log the fields in a `SelectionSet` without cloning. Writes
@@ -115,20 +127,27 @@ impl Query {
         max_complexity: Option<u64>,
         max_depth: u8,
     ) -> Result<Arc<Self>, Vec<QueryExecutionError>> {
-        let mut operation = None;
+        let mut operations = Vec::new();
         let mut fragments = HashMap::new();
         for defn in query.document.definitions.into_iter() {
             match defn {
-                q::Definition::Operation(op) => match operation {
-                    None => operation = Some(op),
-                    Some(_) => return Err(vec![QueryExecutionError::OperationNameRequired]),
-                },
+                q::Definition::Operation(op) => operations.push(op),
                 q::Definition::Fragment(frag) => {
                     fragments.insert(frag.name.clone(), frag);
                 }
             }
         }
-        let operation = operation.ok_or(QueryExecutionError::OperationNameRequired)?;
+        let operation = match &query.operation_name {
+            None if operations.len() == 1 => operations.pop().unwrap(),
+            None => return Err(vec![QueryExecutionError::OperationNameRequired]),
+            Some(name) => {
+                let pos = operations
+                    .iter()
+                    .position(|op| qast::get_operation_name(op) == Some(name.as_str()))
+                    .ok_or_else(|| QueryExecutionError::OperationNotFound(name.clone()))?;
+                operations.swap_remove(pos)
+            }
+        };
 
         let variables = coerce_variables(schema.as_ref(), &operation, query.variables)?;
         let (kind, selection_set) = match operation {
@@ -140,10 +159,13 @@ impl Query {
             q::OperationDefinition::Subscription(q::Subscription { selection_set, .. }) => {
                 (Kind::Subscription, selection_set)
             }
-            q::OperationDefinition::Mutation(_) => {
-                return Err(vec![QueryExecutionError::NotSupported(
-                    "Mutations are not supported".to_owned(),
-                )])
+            q::OperationDefinition::Mutation(q::Mutation { selection_set, .. }) => {
+                if schema.mutation_type.is_none() {
+                    return Err(vec![QueryExecutionError::NotSupported(
+                        "Mutations are not supported".to_owned(),
+                    )]);
+                }
+                (Kind::Mutation, selection_set)
             }
         };
 
@@ -190,6 +212,7 @@ impl Query {
     /// `Deny` and `Allow` otherwise.
     pub fn block_constraint(
         &self,
+        scalars: &ScalarCoercions,
     ) -> Result<HashMap<BlockConstraint, (q::SelectionSet, ErrorPolicy)>, Vec<QueryExecutionError>>
     {
         use graphql_parser::query::Selection::Field;
@@ -202,13 +225,14 @@ impl Query {
             _ => None,
         }) {
             let query_ty = self.schema.query_type.as_ref();
-            let args = match crate::execution::coerce_argument_values(self, query_ty, field) {
-                Ok(args) => args,
-                Err(errs) => {
-                    errors.extend(errs);
-                    continue;
-                }
-            };
+            let args =
+                match crate::execution::coerce_argument_values(self, query_ty, field, scalars) {
+                    Ok(args) => args,
+                    Err(errs) => {
+                        errors.extend(errs);
+                        continue;
+                    }
+                };
 
             let bc = match args.get("block") {
                 Some(bc) => BlockConstraint::try_from_value(bc).map_err(|_| {
@@ -285,7 +309,7 @@ impl Query {
     pub fn is_query(&self) -> bool {
         match self.kind {
             Kind::Query => true,
-            Kind::Subscription => false,
+            Kind::Subscription | Kind::Mutation => false,
         }
     }
 
@@ -293,7 +317,28 @@ impl Query {
     pub fn is_subscription(&self) -> bool {
         match self.kind {
             Kind::Subscription => true,
-            Kind::Query => false,
+            Kind::Query | Kind::Mutation => false,
+        }
+    }
+
+    /// Return `true` if this is a mutation, not a query or a subscription
+    pub fn is_mutation(&self) -> bool {
+        match self.kind {
+            Kind::Mutation => true,
+            Kind::Query | Kind::Subscription => false,
+        }
+    }
+
+    /// The type against which the toplevel fields of this query are
+    /// checked. Subscriptions use the same fields as queries
+    fn root_type(&self) -> &s::ObjectType {
+        match self.kind {
+            Kind::Mutation => self
+                .schema
+                .mutation_type
+                .as_ref()
+                .expect("mutations are only accepted for schemas with a mutation type"),
+            Kind::Query | Kind::Subscription => self.schema.query_type.as_ref(),
         }
     }
 
@@ -355,7 +400,11 @@ impl Query {
     /// If the query is invalid, returns `Ok(0)` so that execution proceeds and
     /// gives a proper error.
     fn complexity(&self, max_depth: u8) -> Result<u64, QueryExecutionError> {
-        let root_type = sast::get_root_query_type_def(self.schema.document()).unwrap();
+        let root_type = self
+            .schema
+            .document()
+            .get_named_type(&self.root_type().name)
+            .unwrap();
 
         match self.complexity_inner(
             root_type,
@@ -376,11 +425,48 @@ impl Query {
         }
     }
 
+    /// Check that `selection_set`, including any fragments it uses, does
+    /// not select any of the introspection fields `__schema`, `__type`, or
+    /// `__typename`. This is used to reject such queries when introspection
+    /// is disabled
+    pub fn validate_no_introspection(
+        &self,
+        selection_set: &q::SelectionSet,
+    ) -> Result<(), QueryExecutionError> {
+        for selection in &selection_set.items {
+            match selection {
+                q::Selection::Field(field) => {
+                    if field.name == "__schema"
+                        || field.name == "__type"
+                        || field.name == "__typename"
+                    {
+                        return Err(QueryExecutionError::IntrospectionDisabled(
+                            field.position,
+                            field.name.clone(),
+                        ));
+                    }
+                    self.validate_no_introspection(&field.selection_set)?;
+                }
+                q::Selection::FragmentSpread(spread) => {
+                    // Undefined and cyclical fragments were already
+                    // rejected when the query was constructed
+                    if let Some(fragment) = self.fragments.get(&spread.fragment_name) {
+                        self.validate_no_introspection(&fragment.selection_set)?;
+                    }
+                }
+                q::Selection::InlineFragment(fragment) => {
+                    self.validate_no_introspection(&fragment.selection_set)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn validate_fields(&self) -> Result<(), Vec<QueryExecutionError>> {
-        let root_type = self.schema.document().get_root_query_type().unwrap();
+        let root_type = self.root_type();
 
         let errors =
-            self.validate_fields_inner(&"Query".to_owned(), root_type.into(), &self.selection_set);
+            self.validate_fields_inner(&root_type.name, root_type.into(), &self.selection_set);
         if errors.len() == 0 {
             Ok(())
         } else {
@@ -388,7 +474,11 @@ impl Query {
         }
     }
 
-    // Checks for invalid selections.
+    // Checks for invalid selections: unknown fields, missing required
+    // arguments, and selection sets that are missing on composite fields
+    // or present on leaf fields. Since this visits every field in the
+    // query once, it is also where we log the first use of each deprecated
+    // field of a deployment
     fn validate_fields_inner(
         &self,
         type_name: &str,
@@ -404,15 +494,75 @@ impl Query {
                 match selection {
                     q::Selection::Field(field) => match get_field(ty, &field.name) {
                         Some(s_field) => {
+                            if let Some(deprecated) = s_field.find_directive("deprecated") {
+                                let key = (
+                                    self.schema.id().clone(),
+                                    type_name.to_owned(),
+                                    field.name.clone(),
+                                );
+                                if DEPRECATION_WARNINGS.lock().unwrap().insert(key) {
+                                    let reason = deprecated
+                                        .argument("reason")
+                                        .and_then(|reason| reason.as_str())
+                                        .unwrap_or("No longer supported");
+                                    warn!(self.logger, "Query uses deprecated field";
+                                          "type" => type_name,
+                                          "field" => &field.name,
+                                          "reason" => reason);
+                                }
+                            }
+
+                            errors.extend(
+                                s_field
+                                    .arguments
+                                    .iter()
+                                    .filter(|arg| {
+                                        sast::is_non_null_type(&arg.value_type)
+                                            && arg.default_value.is_none()
+                                            && !field
+                                                .arguments
+                                                .iter()
+                                                .any(|(name, _)| name == &arg.name)
+                                    })
+                                    .map(|arg| {
+                                        QueryExecutionError::MissingArgumentError(
+                                            field.position,
+                                            arg.name.clone(),
+                                        )
+                                    }),
+                            );
+
                             let base_type = s_field.field_type.get_base_type();
-                            if get_named_type(schema, base_type).is_none() {
-                                errors.push(QueryExecutionError::NamedTypeError(base_type.into()));
-                            } else if let Some(ty) = object_or_interface(schema, base_type) {
-                                errors.extend(self.validate_fields_inner(
-                                    base_type,
-                                    ty,
-                                    &field.selection_set,
-                                ))
+                            match get_named_type(schema, base_type) {
+                                None => errors
+                                    .push(QueryExecutionError::NamedTypeError(base_type.into())),
+                                Some(s::TypeDefinition::Scalar(_))
+                                | Some(s::TypeDefinition::Enum(_)) => {
+                                    // Leaf fields have no fields that could be selected
+                                    errors.extend(field.selection_set.items.iter().filter_map(
+                                        |selection| match selection {
+                                            q::Selection::Field(field) => {
+                                                Some(QueryExecutionError::UnknownField(
+                                                    field.position,
+                                                    base_type.into(),
+                                                    field.name.clone(),
+                                                ))
+                                            }
+                                            _ => None,
+                                        },
+                                    ))
+                                }
+                                Some(_) if field.selection_set.items.is_empty() => errors
+                                    .push(QueryExecutionError::EmptySelectionSet(base_type.into())),
+                                Some(_) => {
+                                    if let Some(ty) = object_or_interface(schema, base_type) {
+                                        errors.extend(self.validate_fields_inner(
+                                            base_type,
+                                            ty,
+                                            &field.selection_set,
+                                        ))
+                                    }
+                                }
                             }
                         }
                         None => errors.push(QueryExecutionError::UnknownField(
@@ -497,7 +647,7 @@ impl Query {
 
                             // `Scalar` and `Enum` cannot have selection sets.
                             // `InputObject` can't appear in a selection.
-                            // `Union` is not yet supported.
+                            // `Union` has no fields of its own.
                             s::TypeDefinition::Scalar(_)
                             | s::TypeDefinition::Enum(_)
                             | s::TypeDefinition::InputObject(_)
@@ -642,7 +792,14 @@ fn coerce_variable(
 
     let resolver = |name: &str| schema.document().get_named_type(name);
 
-    coerce_value(value, &variable_def.var_type, &resolver, &HashMap::new()).map_err(|value| {
+    coerce_value(
+        value,
+        &variable_def.var_type,
+        &resolver,
+        &HashMap::new(),
+        &ScalarCoercions::default(),
+    )
+    .map_err(|value| {
         vec![QueryExecutionError::InvalidArgumentError(
             variable_def.position,
             variable_def.name.to_owned(),
@@ -86,6 +86,52 @@ pub trait Resolver: Sized + Send + Sync + 'static {
         Ok(value.unwrap_or(r::Value::Null))
     }
 
+    /// Resolves a value for a given union type. The value must be an
+    /// object with a `__typename` so that its concrete type can be
+    /// determined with `resolve_abstract_type`
+    fn resolve_union_value(
+        &self,
+        _field: &q::Field,
+        _union_type: &s::UnionType,
+        value: Option<r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        Ok(value.unwrap_or(r::Value::Null))
+    }
+
+    /// Resolves a list of values for a given union type.
+    fn resolve_union_values(
+        &self,
+        _field: &q::Field,
+        _union_type: &s::UnionType,
+        value: Option<r::Value>,
+    ) -> Result<r::Value, Vec<QueryExecutionError>> {
+        Ok(value.unwrap_or(r::Value::Null))
+    }
+
+    /// The suffix that marks `Int` fields as counts of the list field
+    /// whose name is the rest of the field name. With the suffix `Count`,
+    /// the field `tokensCount` counts the entries of the list field
+    /// `tokens` of the same type. Such a field is resolved with
+    /// `resolve_entity_count` unless the parent object already has a value
+    /// for it. Returning `None` turns counting off
+    fn count_field_suffix(&self) -> Option<&str> {
+        None
+    }
+
+    /// Resolves the number of entries that `list_field` of `object_type`
+    /// would have. The `arguments` are those of the count field and
+    /// usually repeat the filters of the list field
+    fn resolve_entity_count(
+        &self,
+        _object_type: &s::ObjectType,
+        _list_field: &s::Field,
+        _arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        Err(QueryExecutionError::NotSupported(String::from(
+            "Counting entities is not supported by this resolver",
+        )))
+    }
+
     // Resolves an abstract type into the specific type of an object.
     fn resolve_abstract_type<'a>(
         &self,
@@ -109,6 +155,20 @@ pub trait Resolver: Sized + Send + Sync + 'static {
         }
     }
 
+    /// Resolves a field of the root mutation type. Mutation fields are
+    /// resolved one at a time, in the order in which they appear in the
+    /// query
+    fn resolve_mutation(
+        &self,
+        _field: &q::Field,
+        _field_definition: &s::Field,
+        _arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        Err(QueryExecutionError::NotSupported(String::from(
+            "Mutations are not supported by this resolver",
+        )))
+    }
+
     // Resolves a change stream for a given field.
     fn resolve_field_stream(
         &self,
@@ -0,0 +1,72 @@
+use graph::data::graphql::object;
+use graph::prelude::r;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Timing information for the resolution of one field
+struct ResolverTrace {
+    parent_type: String,
+    field_name: String,
+    /// When we started resolving the field, relative to the start of
+    /// the trace
+    start_offset: Duration,
+    duration: Duration,
+}
+
+/// Collects how long it took to resolve each field of a query. We only
+/// create a `Trace` when tracing was requested so that queries that do not
+/// need one do not pay for it
+pub(crate) struct Trace {
+    start: Instant,
+    resolvers: Mutex<Vec<ResolverTrace>>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Trace {
+            start: Instant::now(),
+            resolvers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Record that resolving `field_name` on `parent_type` started at
+    /// `start` and just finished
+    pub fn record(&self, parent_type: &str, field_name: &str, start: Instant) {
+        let trace = ResolverTrace {
+            parent_type: parent_type.to_owned(),
+            field_name: field_name.to_owned(),
+            start_offset: start.saturating_duration_since(self.start),
+            duration: start.elapsed(),
+        };
+        self.resolvers.lock().unwrap().push(trace);
+    }
+
+    /// Turn the trace into a value suitable for the `tracing` extension of
+    /// a query result. All durations are in nanoseconds
+    pub fn to_value(&self) -> r::Value {
+        fn nanos(duration: Duration) -> r::Value {
+            r::Value::Int(duration.as_nanos() as i64)
+        }
+
+        let resolvers = self
+            .resolvers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|trace| {
+                object! {
+                    parentType: trace.parent_type.clone(),
+                    fieldName: trace.field_name.clone(),
+                    startOffset: nanos(trace.start_offset),
+                    duration: nanos(trace.duration),
+                }
+            })
+            .collect::<Vec<_>>();
+        object! {
+            duration: nanos(self.start.elapsed()),
+            execution: object! {
+                resolvers: resolvers,
+            },
+        }
+    }
+}
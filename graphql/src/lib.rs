@@ -32,7 +32,7 @@ pub mod prelude {
     pub use super::schema::{api_schema, ast::is_list, ast::validate_entity, APISchemaError};
     pub use super::store::{build_query, StoreResolver};
     pub use super::subscription::SubscriptionExecutionOptions;
-    pub use super::values::MaybeCoercible;
+    pub use super::values::{MaybeCoercible, ScalarCoercion, ScalarCoercions};
 
     pub use super::runner::GraphQlRunner;
     pub use graph::prelude::s::ObjectType;
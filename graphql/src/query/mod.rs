@@ -5,6 +5,7 @@ use std::time::Instant;
 use graph::data::graphql::effort::LoadManager;
 
 use crate::execution::*;
+use crate::values::ScalarCoercions;
 
 /// Utilities for working with GraphQL query ASTs.
 pub mod ast;
@@ -27,6 +28,20 @@ pub struct QueryExecutionOptions<R> {
     pub max_skip: u32,
 
     pub load_manager: Arc<LoadManager>,
+
+    /// Whether to record how long resolving each field takes and return
+    /// that in the `tracing` extension of the result
+    pub trace: bool,
+
+    /// How many elements of a list to complete at the same time
+    pub list_concurrency: usize,
+
+    /// Whether to allow queries that use the introspection fields
+    /// `__schema`, `__type`, and `__typename`
+    pub allow_introspection: bool,
+    /// The coercions to use for scalar arguments. Variables are coerced
+    /// when the query is prepared and always use the default coercions
+    pub scalar_coercions: Arc<ScalarCoercions>,
 }
 
 /// Executes a query and returns a result.
@@ -48,18 +63,61 @@ where
         deadline: options.deadline,
         max_first: options.max_first,
         max_skip: options.max_skip,
+        list_concurrency: options.list_concurrency,
         cache_status: Default::default(),
+        trace: if options.trace {
+            Some(Trace::new())
+        } else {
+            None
+        },
+        scalar_coercions: options.scalar_coercions,
     });
 
-    if !query.is_query() {
+    if query.is_subscription() {
         return Arc::new(
-            QueryExecutionError::NotSupported("Only queries are supported".to_string()).into(),
+            QueryExecutionError::NotSupported(
+                "Only queries and mutations are supported".to_string(),
+            )
+            .into(),
         );
     }
     let selection_set = selection_set
         .map(Arc::new)
         .unwrap_or_else(|| query.selection_set.cheap_clone());
 
+    if !options.allow_introspection {
+        if let Err(e) = query.validate_no_introspection(&selection_set) {
+            return Arc::new(e.into());
+        }
+    }
+
+    // Mutations are never cached, and their fields must be executed serially
+    if query.is_mutation() {
+        let mutation_type = query
+            .schema
+            .mutation_type
+            .cheap_clone()
+            .expect("mutations are only accepted for schemas with a mutation type");
+        let _permit = ctx.resolver.query_permit().await;
+        let execute_ctx = ctx.cheap_clone();
+        let execute_selection_set = selection_set.cheap_clone();
+        let result = graph::spawn_blocking_allow_panic(move || {
+            let mut result = QueryResult::from(execute_mutation_selection_set(
+                &execute_ctx,
+                &execute_selection_set,
+                &mutation_type,
+            ));
+            if let Some(trace) = &execute_ctx.trace {
+                result.set_extension("tracing", trace.to_value());
+            }
+            result.deployment = Some(execute_ctx.query.schema.id().clone());
+            result
+        })
+        .await
+        .unwrap_or_else(|e| QueryExecutionError::Panic(e.to_string()).into());
+        return Arc::new(result);
+    }
+
     // Execute top-level `query { ... }` and `{ ... }` expressions.
     let query_type = ctx.query.schema.query_type.cheap_clone();
     let start = Instant::now();
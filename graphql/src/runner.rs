@@ -4,7 +4,9 @@ use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use crate::prelude::{QueryExecutionOptions, StoreResolver, SubscriptionExecutionOptions};
+use crate::prelude::{
+    QueryExecutionOptions, ScalarCoercions, StoreResolver, SubscriptionExecutionOptions,
+};
 use crate::query::execute_query;
 use crate::subscription::execute_prepared_subscription;
 use graph::prelude::MetricsRegistry;
@@ -77,6 +79,7 @@ pub struct GraphQlRunner<S, SM> {
     subscription_manager: Arc<SM>,
     load_manager: Arc<LoadManager>,
     result_size: Arc<ResultSizeMetrics>,
+    scalar_coercions: Arc<ScalarCoercions>,
 }
 
 lazy_static! {
@@ -105,6 +108,25 @@ lazy_static! {
         .map(|s| u32::from_str(&s)
             .unwrap_or_else(|_| panic!("failed to parse env var GRAPH_GRAPHQL_MAX_SKIP")))
         .unwrap_or(std::u32::MAX);
+    // How many elements of a list to complete at the same time; the
+    // default of 1 completes them one after the other
+    pub(crate) static ref GRAPHQL_LIST_CONCURRENCY: usize = env::var("GRAPH_GRAPHQL_LIST_CONCURRENCY")
+        .ok()
+        .map(|s| usize::from_str(&s)
+            .unwrap_or_else(|_| panic!("failed to parse env var GRAPH_GRAPHQL_LIST_CONCURRENCY")))
+        .unwrap_or(1);
+    // Reject queries that use introspection
+    static ref GRAPHQL_DISABLE_INTROSPECTION: bool = env::var("GRAPH_GRAPHQL_DISABLE_INTROSPECTION")
+        .ok()
+        .map(|s| s == "true")
+        .unwrap_or(false);
+    // Record how long resolving each field took and return that in the
+    // `tracing` extension of query results. Traced queries bypass the
+    // query cache
+    static ref GRAPHQL_TRACE: bool = env::var("GRAPH_GRAPHQL_TRACE")
+        .ok()
+        .map(|s| s == "true")
+        .unwrap_or(false);
     // Allow skipping the check whether a deployment has changed while
     // we were running a query. Once we are sure that the check mechanism
     // is reliable, this variable should be removed
@@ -141,6 +163,7 @@ where
             subscription_manager,
             load_manager,
             result_size,
+            scalar_coercions: Arc::new(ScalarCoercions::default()),
         }
     }
 
@@ -222,7 +245,7 @@ where
                 query.query_text.as_ref(),
             )
             .to_result()?;
-        let by_block_constraint = query.block_constraint()?;
+        let by_block_constraint = query.block_constraint(&self.scalar_coercions)?;
         let mut max_block = 0;
         let mut result: QueryResults = QueryResults::empty();
 
@@ -249,6 +272,10 @@ where
                     max_first: max_first.unwrap_or(*GRAPHQL_MAX_FIRST),
                     max_skip: max_skip.unwrap_or(*GRAPHQL_MAX_SKIP),
                     load_manager: self.load_manager.clone(),
+                    trace: *GRAPHQL_TRACE,
+                    list_concurrency: *GRAPHQL_LIST_CONCURRENCY,
+                    allow_introspection: !*GRAPHQL_DISABLE_INTROSPECTION,
+                    scalar_coercions: self.scalar_coercions.cheap_clone(),
                 },
             )
             .await;
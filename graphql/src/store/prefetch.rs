@@ -983,7 +983,12 @@ fn execute_field(
     field_definition: &s::Field,
     collected_column_names: AttributeNamesByObjectType<'_>,
 ) -> Result<Vec<Node>, Vec<QueryExecutionError>> {
-    let argument_values = crate::execution::coerce_argument_values(&ctx.query, object_type, field)?;
+    let argument_values = crate::execution::coerce_argument_values(
+        &ctx.query,
+        object_type,
+        field,
+        &ctx.scalar_coercions,
+    )?;
     let multiplicity = if sast::is_list_or_non_null_list_field(field_definition) {
         ChildMultiplicity::Many
     } else {
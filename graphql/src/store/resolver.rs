@@ -313,14 +313,15 @@ impl Resolver for StoreResolver {
     }
 
     fn post_process(&self, result: &mut QueryResult) -> Result<(), anyhow::Error> {
-        // Post-processing is only necessary for queries with indexing errors, and no query errors.
-        if !self.has_non_fatal_errors || result.has_errors() {
+        // Post-processing is only necessary for queries with indexing errors.
+        if !self.has_non_fatal_errors {
             return Ok(());
         }
 
-        // Add the "indexing_error" to the response.
-        assert!(result.errors_mut().is_empty());
-        *result.errors_mut() = vec![QueryError::IndexingError];
+        // Add the "indexing_error" to the response. The query may have
+        // failed in places already, and can still return partial data,
+        // which we need to omit below just like for a successful query
+        result.errors_mut().push(QueryError::IndexingError);
 
         match self.error_policy {
             // If indexing errors are denied, we omit results, except for the `_meta` response.
@@ -9,7 +9,7 @@ use graph::{components::store::SubscriptionManager, prelude::*};
 use crate::runner::ResultSizeMetrics;
 use crate::{
     execution::*,
-    prelude::{BlockConstraint, StoreResolver},
+    prelude::{BlockConstraint, ScalarCoercions, StoreResolver},
     schema::api::ErrorPolicy,
 };
 
@@ -96,7 +96,10 @@ fn create_source_event_stream(
         deadline: None,
         max_first: options.max_first,
         max_skip: options.max_skip,
+        list_concurrency: 1,
         cache_status: Default::default(),
+        trace: None,
+        scalar_coercions: Arc::new(ScalarCoercions::default()),
     };
 
     let subscription_type = ctx
@@ -122,7 +125,12 @@ fn create_source_event_stream(
 
     let fields = grouped_field_set.get_index(0).unwrap();
     let field = fields.1[0];
-    let argument_values = coerce_argument_values(&ctx.query, subscription_type.as_ref(), field)?;
+    let argument_values = coerce_argument_values(
+        &ctx.query,
+        subscription_type.as_ref(),
+        field,
+        &ctx.scalar_coercions,
+    )?;
 
     resolve_field_stream(&ctx, &subscription_type, field, argument_values)
 }
@@ -215,7 +223,10 @@ async fn execute_subscription_event(
         deadline: timeout.map(|t| Instant::now() + t),
         max_first,
         max_skip,
+        list_concurrency: 1,
         cache_status: Default::default(),
+        trace: None,
+        scalar_coercions: Arc::new(ScalarCoercions::default()),
     });
 
     let subscription_type = match ctx.query.schema.subscription_type.as_ref() {
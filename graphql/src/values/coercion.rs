@@ -1,7 +1,10 @@
 use crate::schema;
-use graph::prelude::s::{EnumType, InputValue, ScalarType, Type, TypeDefinition};
+use graph::data::graphql::TypeExt;
+use graph::data::store::scalar;
+use graph::prelude::s::{EnumType, InputObjectType, InputValue, ScalarType, Type, TypeDefinition};
 use graph::prelude::{q, r, QueryExecutionError};
 use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
 
 /// A GraphQL value that can be coerced according to a type.
 pub trait MaybeCoercible<T> {
@@ -56,19 +59,86 @@ impl MaybeCoercible<ScalarType> for q::Value {
     }
 }
 
+/// Coerces a value for a scalar type. On error, the value is returned as
+/// `Err(value)`.
+pub type ScalarCoercion = fn(q::Value) -> Result<r::Value, q::Value>;
+
+/// Coercions for scalars that need more than what `MaybeCoercible` does,
+/// keyed by the name of the scalar. A registered coercion replaces the
+/// built-in one for its scalar. The default registry validates `BigInt`
+/// and `BigDecimal` values.
+#[derive(Clone)]
+pub struct ScalarCoercions {
+    coercions: HashMap<String, ScalarCoercion>,
+}
+
+impl ScalarCoercions {
+    /// A registry that uses the built-in coercion for every scalar.
+    pub fn empty() -> Self {
+        ScalarCoercions {
+            coercions: HashMap::new(),
+        }
+    }
+
+    /// Coerce values of the scalar `name` with `coercion`, replacing any
+    /// coercion that was registered for it before.
+    pub fn register(&mut self, name: impl Into<String>, coercion: ScalarCoercion) {
+        self.coercions.insert(name.into(), coercion);
+    }
+
+    fn coerce(&self, value: q::Value, ty: &ScalarType) -> Result<r::Value, q::Value> {
+        match (self.coercions.get(&ty.name), value) {
+            (_, q::Value::Null) => Ok(r::Value::Null),
+            (Some(coercion), value) => coercion(value),
+            (None, value) => value.coerce(ty),
+        }
+    }
+}
+
+impl Default for ScalarCoercions {
+    fn default() -> Self {
+        let mut coercions = ScalarCoercions::empty();
+        coercions.register("BigInt", coerce_big_int);
+        coercions.register("BigDecimal", coerce_big_decimal);
+        coercions
+    }
+}
+
+fn coerce_big_int(value: q::Value) -> Result<r::Value, q::Value> {
+    match value {
+        q::Value::String(s) if scalar::BigInt::from_str(&s).is_ok() => Ok(r::Value::String(s)),
+        q::Value::Int(n) => Ok(r::Value::String(
+            n.as_i64().ok_or(q::Value::Int(n))?.to_string(),
+        )),
+        value => Err(value),
+    }
+}
+
+fn coerce_big_decimal(value: q::Value) -> Result<r::Value, q::Value> {
+    match value {
+        q::Value::String(s) if scalar::BigDecimal::from_str(&s).is_ok() => Ok(r::Value::String(s)),
+        q::Value::Float(f) => Ok(r::Value::String(f.to_string())),
+        q::Value::Int(i) => Ok(r::Value::String(
+            i.as_i64().ok_or(q::Value::Int(i))?.to_string(),
+        )),
+        value => Err(value),
+    }
+}
+
 /// On error, the `value` is returned as `Err(value)`.
 fn coerce_to_definition<'a>(
     value: q::Value,
     definition: &str,
     resolver: &impl Fn(&str) -> Option<&'a TypeDefinition>,
     variables: &HashMap<String, r::Value>,
+    scalars: &ScalarCoercions,
 ) -> Result<r::Value, q::Value> {
     match resolver(definition).ok_or_else(|| value.clone())? {
         // Accept enum values if they match a value in the enum type
         TypeDefinition::Enum(t) => value.coerce(t),
 
-        // Try to coerce Scalar values
-        TypeDefinition::Scalar(t) => value.coerce(t),
+        // Try to coerce Scalar values, preferring registered coercions
+        TypeDefinition::Scalar(t) => scalars.coerce(value, t),
 
         // Try to coerce InputObject values
         TypeDefinition::InputObject(t) => match value {
@@ -83,12 +153,16 @@ fn coerce_to_definition<'a>(
                         .ok_or_else(|| object_for_error.clone())?;
                     coerced_object.insert(
                         name.clone(),
-                        match coerce_input_value(Some(value), def, resolver, variables) {
+                        match coerce_input_value(Some(value), def, resolver, variables, scalars) {
                             Err(_) | Ok(None) => return Err(object_for_error),
                             Ok(Some(v)) => v,
                         },
                     );
                 }
+                // Required fields must be present
+                if missing_required_field(t, &coerced_object).is_some() {
+                    return Err(object_for_error);
+                }
                 Ok(r::Value::Object(coerced_object))
             }
             _ => Err(value),
@@ -99,6 +173,50 @@ fn coerce_to_definition<'a>(
     }
 }
 
+/// Return the first field of `ty` that is required but has no value in
+/// `object`
+fn missing_required_field<'t, V>(
+    ty: &'t InputObjectType,
+    object: &BTreeMap<String, V>,
+) -> Option<&'t InputValue> {
+    ty.fields.iter().find(|field| {
+        schema::ast::is_non_null_type(&field.value_type)
+            && field.default_value.is_none()
+            && !object.contains_key(&field.name)
+    })
+}
+
+/// When `value` could not be coerced to `ty`, find the input object field
+/// that is to blame. Returns the path to that field, relative to `value`,
+/// and the offending value for it; the offending value is `null` for a
+/// required field that is missing. Returns `None` if `value` is not an
+/// input object or if we can not single out one field
+fn find_invalid_field<'a>(
+    value: &q::Value,
+    ty: &Type,
+    resolver: &impl Fn(&str) -> Option<&'a TypeDefinition>,
+    variable_values: &HashMap<String, r::Value>,
+    scalars: &ScalarCoercions,
+) -> Option<(String, q::Value)> {
+    let (t, object) = match (resolver(ty.get_base_type()), value) {
+        (Some(TypeDefinition::InputObject(t)), q::Value::Object(object)) => (t, object),
+        _ => return None,
+    };
+
+    for (name, value) in object {
+        let def = match t.fields.iter().find(|f| &f.name == name) {
+            Some(def) => def,
+            None => return Some((name.to_owned(), value.clone())),
+        };
+        if let Err(QueryExecutionError::InvalidArgumentError(_, path, value)) =
+            coerce_input_value(Some(value.clone()), def, resolver, variable_values, scalars)
+        {
+            return Some((path, value));
+        }
+    }
+    missing_required_field(t, object).map(|field| (field.name.to_owned(), q::Value::Null))
+}
+
 /// Coerces an argument into a GraphQL value.
 ///
 /// `Ok(None)` happens when no value is found for a nullable type.
@@ -107,6 +225,7 @@ pub(crate) fn coerce_input_value<'a>(
     def: &InputValue,
     resolver: &impl Fn(&str) -> Option<&'a TypeDefinition>,
     variable_values: &HashMap<String, r::Value>,
+    scalars: &ScalarCoercions,
 ) -> Result<Option<r::Value>, QueryExecutionError> {
     if let Some(q::Value::Variable(name)) = value {
         value = variable_values.get(&name).cloned().map(Into::into);
@@ -131,9 +250,24 @@ pub(crate) fn coerce_input_value<'a>(
     };
 
     Ok(Some(
-        coerce_value(value, &def.value_type, resolver, variable_values).map_err(|val| {
-            QueryExecutionError::InvalidArgumentError(def.position, def.name.to_owned(), val)
-        })?,
+        coerce_value(value, &def.value_type, resolver, variable_values, scalars).map_err(
+            |val| {
+                // For input objects, point at the field that is the problem
+                match find_invalid_field(&val, &def.value_type, resolver, variable_values, scalars)
+                {
+                    Some((path, val)) => QueryExecutionError::InvalidArgumentError(
+                        def.position,
+                        format!("{}.{}", def.name, path),
+                        val,
+                    ),
+                    None => QueryExecutionError::InvalidArgumentError(
+                        def.position,
+                        def.name.to_owned(),
+                        val,
+                    ),
+                }
+            },
+        )?,
     ))
 }
 
@@ -143,6 +277,7 @@ pub(crate) fn coerce_value<'a>(
     ty: &Type,
     resolver: &impl Fn(&str) -> Option<&'a TypeDefinition>,
     variable_values: &HashMap<String, r::Value>,
+    scalars: &ScalarCoercions,
 ) -> Result<r::Value, q::Value> {
     match (ty, value) {
         // Null values cannot be coerced into non-null types.
@@ -156,7 +291,7 @@ pub(crate) fn coerce_value<'a>(
                 Type::NonNullType(ty) => ty,
                 _ => unreachable!(),
             };
-            coerce_value(val, t, resolver, variable_values)
+            coerce_value(val, t, resolver, variable_values, scalars)
         }
 
         // Nullable types can be null.
@@ -168,7 +303,7 @@ pub(crate) fn coerce_value<'a>(
                 Type::NamedType(name) => name,
                 _ => unreachable!(),
             };
-            coerce_to_definition(val, name, resolver, variable_values)
+            coerce_to_definition(val, name, resolver, variable_values, scalars)
         }
 
         // List values are coercible if their values are coercible into the
@@ -182,7 +317,7 @@ pub(crate) fn coerce_value<'a>(
 
             // Coerce the list values individually
             for value in values {
-                coerced_values.push(coerce_value(value, t, resolver, variable_values)?);
+                coerced_values.push(coerce_value(value, t, resolver, variable_values, scalars)?);
             }
 
             Ok(r::Value::List(coerced_values))
@@ -195,12 +330,13 @@ pub(crate) fn coerce_value<'a>(
 
 #[cfg(test)]
 mod tests {
+    use graph::data::graphql::DocumentExt;
     use graph::prelude::{q, r::Value};
     use graphql_parser::schema::{EnumType, EnumValue, ScalarType, TypeDefinition};
     use graphql_parser::Pos;
     use std::collections::HashMap;
 
-    use super::coerce_to_definition;
+    use super::{coerce_input_value, coerce_to_definition, ScalarCoercions};
 
     #[test]
     fn coercion_using_enum_type_definitions_is_correct() {
@@ -224,7 +360,8 @@ mod tests {
                 q::Value::Enum("ValidVariant".to_string()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::Enum("ValidVariant".to_string()))
         );
@@ -234,7 +371,8 @@ mod tests {
             q::Value::Enum("InvalidVariant".to_string()),
             "",
             &resolver,
-            &HashMap::new()
+            &HashMap::new(),
+            &ScalarCoercions::default()
         )
         .is_err());
 
@@ -244,7 +382,8 @@ mod tests {
                 q::Value::String("ValidVariant".to_string()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::Enum("ValidVariant".to_string())),
         );
@@ -254,7 +393,8 @@ mod tests {
             q::Value::String("InvalidVariant".to_string()),
             "",
             &resolver,
-            &HashMap::new()
+            &HashMap::new(),
+            &ScalarCoercions::default()
         )
         .is_err());
     }
@@ -271,11 +411,23 @@ mod tests {
 
         // We can coerce from Value::Boolean -> TypeDefinition::Scalar(Boolean)
         assert_eq!(
-            coerce_to_definition(q::Value::Boolean(true), "", &resolver, &HashMap::new()),
+            coerce_to_definition(
+                q::Value::Boolean(true),
+                "",
+                &resolver,
+                &HashMap::new(),
+                &ScalarCoercions::default()
+            ),
             Ok(Value::Boolean(true))
         );
         assert_eq!(
-            coerce_to_definition(q::Value::Boolean(false), "", &resolver, &HashMap::new()),
+            coerce_to_definition(
+                q::Value::Boolean(false),
+                "",
+                &resolver,
+                &HashMap::new(),
+                &ScalarCoercions::default()
+            ),
             Ok(Value::Boolean(false))
         );
 
@@ -284,24 +436,36 @@ mod tests {
             q::Value::String("true".to_string()),
             "",
             &resolver,
-            &HashMap::new()
+            &HashMap::new(),
+            &ScalarCoercions::default()
         )
         .is_err());
         assert!(coerce_to_definition(
             q::Value::String("false".to_string()),
             "",
             &resolver,
-            &HashMap::new()
+            &HashMap::new(),
+            &ScalarCoercions::default()
         )
         .is_err());
 
         // We don't support going from Value::Float -> TypeDefinition::Scalar(Boolean)
-        assert!(
-            coerce_to_definition(q::Value::Float(1.0), "", &resolver, &HashMap::new()).is_err()
-        );
-        assert!(
-            coerce_to_definition(q::Value::Float(0.0), "", &resolver, &HashMap::new()).is_err()
-        );
+        assert!(coerce_to_definition(
+            q::Value::Float(1.0),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
+        assert!(coerce_to_definition(
+            q::Value::Float(0.0),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
     }
 
     #[test]
@@ -311,11 +475,23 @@ mod tests {
 
         // We can coerce from Value::Float -> TypeDefinition::Scalar(BigDecimal)
         assert_eq!(
-            coerce_to_definition(q::Value::Float(23.7), "", &resolver, &HashMap::new()),
+            coerce_to_definition(
+                q::Value::Float(23.7),
+                "",
+                &resolver,
+                &HashMap::new(),
+                &ScalarCoercions::default()
+            ),
             Ok(Value::String("23.7".to_string()))
         );
         assert_eq!(
-            coerce_to_definition(q::Value::Float(-5.879), "", &resolver, &HashMap::new()),
+            coerce_to_definition(
+                q::Value::Float(-5.879),
+                "",
+                &resolver,
+                &HashMap::new(),
+                &ScalarCoercions::default()
+            ),
             Ok(Value::String("-5.879".to_string()))
         );
 
@@ -325,7 +501,8 @@ mod tests {
                 q::Value::String("23.7".to_string()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::String("23.7".to_string()))
         );
@@ -334,14 +511,31 @@ mod tests {
                 q::Value::String("-5.879".to_string()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::String("-5.879".to_string())),
         );
 
+        // But not from strings that are not numbers
+        assert!(coerce_to_definition(
+            q::Value::String("twelve".to_string()),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
+
         // We can coerce from Value::Int -> TypeDefinition::Scalar(BigDecimal)
         assert_eq!(
-            coerce_to_definition(q::Value::Int(23.into()), "", &resolver, &HashMap::new()),
+            coerce_to_definition(
+                q::Value::Int(23.into()),
+                "",
+                &resolver,
+                &HashMap::new(),
+                &ScalarCoercions::default()
+            ),
             Ok(Value::String("23".to_string()))
         );
         assert_eq!(
@@ -349,18 +543,29 @@ mod tests {
                 q::Value::Int((-5 as i32).into()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::String("-5".to_string())),
         );
 
         // We don't support going from Value::Boolean -> TypeDefinition::Scalar(Boolean)
-        assert!(
-            coerce_to_definition(q::Value::Boolean(true), "", &resolver, &HashMap::new()).is_err()
-        );
-        assert!(
-            coerce_to_definition(q::Value::Boolean(false), "", &resolver, &HashMap::new()).is_err()
-        );
+        assert!(coerce_to_definition(
+            q::Value::Boolean(true),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
+        assert!(coerce_to_definition(
+            q::Value::Boolean(false),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
     }
 
     #[test]
@@ -374,7 +579,8 @@ mod tests {
                 q::Value::String("foo".to_string()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::String("foo".to_string()))
         );
@@ -383,26 +589,47 @@ mod tests {
                 q::Value::String("bar".to_string()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::String("bar".to_string()))
         );
 
         // We don't support going from Value::Boolean -> TypeDefinition::Scalar(String)
-        assert!(
-            coerce_to_definition(q::Value::Boolean(true), "", &resolver, &HashMap::new()).is_err()
-        );
-        assert!(
-            coerce_to_definition(q::Value::Boolean(false), "", &resolver, &HashMap::new()).is_err()
-        );
+        assert!(coerce_to_definition(
+            q::Value::Boolean(true),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
+        assert!(coerce_to_definition(
+            q::Value::Boolean(false),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
 
         // We don't support going from Value::Float -> TypeDefinition::Scalar(String)
-        assert!(
-            coerce_to_definition(q::Value::Float(23.7), "", &resolver, &HashMap::new()).is_err()
-        );
-        assert!(
-            coerce_to_definition(q::Value::Float(-5.879), "", &resolver, &HashMap::new()).is_err()
-        );
+        assert!(coerce_to_definition(
+            q::Value::Float(23.7),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
+        assert!(coerce_to_definition(
+            q::Value::Float(-5.879),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
     }
 
     #[test]
@@ -416,7 +643,8 @@ mod tests {
                 q::Value::String("foo".to_string()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::String("foo".to_string()))
         );
@@ -425,33 +653,60 @@ mod tests {
                 q::Value::String("bar".to_string()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::String("bar".to_string()))
         );
 
         // And also from Value::Int
         assert_eq!(
-            coerce_to_definition(q::Value::Int(1234.into()), "", &resolver, &HashMap::new()),
+            coerce_to_definition(
+                q::Value::Int(1234.into()),
+                "",
+                &resolver,
+                &HashMap::new(),
+                &ScalarCoercions::default()
+            ),
             Ok(Value::String("1234".to_string()))
         );
 
         // We don't support going from Value::Boolean -> TypeDefinition::Scalar(ID)
-        assert!(
-            coerce_to_definition(q::Value::Boolean(true), "", &resolver, &HashMap::new()).is_err()
-        );
+        assert!(coerce_to_definition(
+            q::Value::Boolean(true),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
 
-        assert!(
-            coerce_to_definition(q::Value::Boolean(false), "", &resolver, &HashMap::new()).is_err()
-        );
+        assert!(coerce_to_definition(
+            q::Value::Boolean(false),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
 
         // We don't support going from Value::Float -> TypeDefinition::Scalar(ID)
-        assert!(
-            coerce_to_definition(q::Value::Float(23.7), "", &resolver, &HashMap::new()).is_err()
-        );
-        assert!(
-            coerce_to_definition(q::Value::Float(-5.879), "", &resolver, &HashMap::new()).is_err()
-        );
+        assert!(coerce_to_definition(
+            q::Value::Float(23.7),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
+        assert!(coerce_to_definition(
+            q::Value::Float(-5.879),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
     }
 
     #[test]
@@ -465,14 +720,21 @@ mod tests {
                 q::Value::String("1234".to_string()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::String("1234".to_string()))
         );
 
         // And also from Value::Int
         assert_eq!(
-            coerce_to_definition(q::Value::Int(1234.into()), "", &resolver, &HashMap::new()),
+            coerce_to_definition(
+                q::Value::Int(1234.into()),
+                "",
+                &resolver,
+                &HashMap::new(),
+                &ScalarCoercions::default()
+            ),
             Ok(Value::String("1234".to_string()))
         );
         assert_eq!(
@@ -480,10 +742,62 @@ mod tests {
                 q::Value::Int((-1234 as i32).into()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::String("-1234".to_string()))
         );
+
+        // But not from strings that are not integers
+        assert!(coerce_to_definition(
+            q::Value::String("12.34".to_string()),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
+        assert!(coerce_to_definition(
+            q::Value::String("0x1234".to_string()),
+            "",
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn registered_scalar_coercion_replaces_builtin() {
+        let bytes_type = TypeDefinition::Scalar(ScalarType::new("Bytes".to_string()));
+        let resolver = |_: &str| Some(&bytes_type);
+
+        fn even_hex(value: q::Value) -> Result<Value, q::Value> {
+            match value {
+                q::Value::String(s) if s.len() % 2 == 0 => Ok(Value::String(s)),
+                value => Err(value),
+            }
+        }
+        let mut scalars = ScalarCoercions::default();
+        scalars.register("Bytes", even_hex);
+
+        let coerce = |s: &str, scalars: &ScalarCoercions| {
+            coerce_to_definition(
+                q::Value::String(s.to_string()),
+                "",
+                &resolver,
+                &HashMap::new(),
+                scalars,
+            )
+        };
+        assert_eq!(
+            coerce("0x21", &scalars),
+            Ok(Value::String("0x21".to_string()))
+        );
+        assert!(coerce("0x21f", &scalars).is_err());
+
+        // Without the registration, the built-in coercion accepts both
+        assert!(coerce("0x21f", &ScalarCoercions::default()).is_ok());
     }
 
     #[test]
@@ -497,7 +811,8 @@ mod tests {
                 q::Value::String("0x21f".to_string()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::String("0x21f".to_string()))
         );
@@ -513,7 +828,8 @@ mod tests {
                 q::Value::Int(13289123.into()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::Int(13289123.into()))
         );
@@ -522,9 +838,146 @@ mod tests {
                 q::Value::Int((-13289123 as i32).into()),
                 "",
                 &resolver,
-                &HashMap::new()
+                &HashMap::new(),
+                &ScalarCoercions::default()
             ),
             Ok(Value::Int((-13289123 as i32).into()))
         );
     }
+
+    const FILTER_SCHEMA: &str = "
+        input Name_filter { first: String, last_in: [String!] }
+        input Range { from: Int!, to: Int }
+        input Person_filter { id: ID, age: Range, name: Name_filter }
+        type Query { people(where: Person_filter): [String] }
+    ";
+
+    fn object(fields: Vec<(&str, q::Value)>) -> q::Value {
+        q::Value::Object(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn coerce_filter(
+        value: q::Value,
+    ) -> Result<Option<Value>, graph::prelude::QueryExecutionError> {
+        let schema = graphql_parser::parse_schema::<String>(FILTER_SCHEMA).unwrap();
+        let resolver = |name: &str| schema.get_named_type(name);
+        let def = match schema.get_named_type("Query") {
+            Some(TypeDefinition::Object(t)) => t.fields[0].arguments[0].clone(),
+            _ => unreachable!("the schema has a Query type"),
+        };
+        coerce_input_value(
+            Some(value),
+            &def,
+            &resolver,
+            &HashMap::new(),
+            &ScalarCoercions::default(),
+        )
+    }
+
+    #[test]
+    fn coerce_nested_filter() {
+        let filter = object(vec![
+            ("id", q::Value::String("1".to_string())),
+            ("age", object(vec![("from", q::Value::Int(18.into()))])),
+            (
+                "name",
+                object(vec![
+                    ("first", q::Value::String("Alice".to_string())),
+                    (
+                        "last_in",
+                        q::Value::List(vec![q::Value::String("Smith".to_string())]),
+                    ),
+                ]),
+            ),
+        ]);
+
+        let exp = Value::Object(
+            vec![
+                ("id".to_string(), Value::String("1".to_string())),
+                (
+                    "age".to_string(),
+                    Value::Object(
+                        vec![("from".to_string(), Value::Int(18.into()))]
+                            .into_iter()
+                            .collect(),
+                    ),
+                ),
+                (
+                    "name".to_string(),
+                    Value::Object(
+                        vec![
+                            ("first".to_string(), Value::String("Alice".to_string())),
+                            (
+                                "last_in".to_string(),
+                                Value::List(vec![Value::String("Smith".to_string())]),
+                            ),
+                        ]
+                        .into_iter()
+                        .collect(),
+                    ),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(Some(exp), coerce_filter(filter).unwrap());
+    }
+
+    #[test]
+    fn invalid_filter_reports_offending_key() {
+        use graph::prelude::QueryExecutionError::InvalidArgumentError;
+
+        #[track_caller]
+        fn check(filter: q::Value, exp_path: &str, exp_value: q::Value) {
+            match coerce_filter(filter) {
+                Err(InvalidArgumentError(_, path, value)) => {
+                    assert_eq!(exp_path, path);
+                    assert_eq!(exp_value, value);
+                }
+                res => panic!("expected an invalid argument error but got {:?}", res),
+            }
+        }
+
+        // Unknown key at the top level
+        check(
+            object(vec![("nmae", object(vec![]))]),
+            "where.nmae",
+            object(vec![]),
+        );
+
+        // Unknown key in a nested filter
+        check(
+            object(vec![(
+                "name",
+                object(vec![("frist", q::Value::String("Alice".to_string()))]),
+            )]),
+            "where.name.frist",
+            q::Value::String("Alice".to_string()),
+        );
+
+        // A null element in a nested list of non-null values
+        check(
+            object(vec![(
+                "name",
+                object(vec![("last_in", q::Value::List(vec![q::Value::Null]))]),
+            )]),
+            "where.name.last_in",
+            q::Value::Null,
+        );
+
+        // A missing required field
+        check(
+            object(vec![(
+                "age",
+                object(vec![("to", q::Value::Int(65.into()))]),
+            )]),
+            "where.age.from",
+            q::Value::Null,
+        );
+    }
 }
@@ -1,4 +1,4 @@
 /// Utilities for coercing GraphQL values based on GraphQL types.
 pub mod coercion;
 
-pub use self::coercion::MaybeCoercible;
+pub use self::coercion::{MaybeCoercible, ScalarCoercion, ScalarCoercions};
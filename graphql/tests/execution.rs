@@ -0,0 +1,986 @@
+//! Tests for query execution that do not need a store. The resolver used
+//! here hands out fixed data for the toplevel fields of the query
+#[macro_use]
+extern crate pretty_assertions;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use graph::data::graphql::{object, IntoValue, ObjectOrInterface};
+use graph::prelude::{
+    async_trait, o, q, r, s, serde_json, slog, tokio, ApiSchema, DeploymentHash, Logger, Query,
+    QueryError, QueryExecutionError, QueryResult, Schema,
+};
+use graph_graphql::prelude::{
+    execute_query, ExecutionContext, Query as PreparedQuery, QueryExecutionOptions, Resolver,
+    ScalarCoercions,
+};
+use test_store::LOAD_MANAGER;
+
+/// Resolver that resolves the toplevel fields of a query from a fixed
+/// object; all nested values must already be contained in that object
+#[derive(Clone)]
+struct FixedResolver {
+    root: r::Value,
+}
+
+#[async_trait]
+impl Resolver for FixedResolver {
+    const CACHEABLE: bool = false;
+
+    fn prefetch(
+        &self,
+        _: &ExecutionContext<Self>,
+        _: &q::SelectionSet,
+    ) -> Result<Option<r::Value>, Vec<QueryExecutionError>> {
+        Ok(Some(self.root.clone()))
+    }
+
+    fn resolve_objects(
+        &self,
+        prefetched_objects: Option<r::Value>,
+        _field: &q::Field,
+        _field_definition: &s::Field,
+        _object_type: ObjectOrInterface<'_>,
+        _arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        Ok(prefetched_objects.unwrap_or(r::Value::Null))
+    }
+
+    fn resolve_object(
+        &self,
+        prefetched_object: Option<r::Value>,
+        _field: &q::Field,
+        _field_definition: &s::Field,
+        _object_type: ObjectOrInterface<'_>,
+        _arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        Ok(prefetched_object.unwrap_or(r::Value::Null))
+    }
+
+    async fn query_permit(&self) -> tokio::sync::OwnedSemaphorePermit {
+        Arc::new(tokio::sync::Semaphore::new(1))
+            .acquire_owned()
+            .await
+            .unwrap()
+    }
+}
+
+/// Resolver that records the order in which mutation fields are resolved
+/// and resolves each of them to the number of mutations resolved before it.
+/// It also remembers the `amount` argument of every mutation that has one
+#[derive(Clone, Default)]
+struct RecordingResolver {
+    calls: Arc<Mutex<Vec<String>>>,
+    amounts: Arc<Mutex<Vec<r::Value>>>,
+}
+
+#[async_trait]
+impl Resolver for RecordingResolver {
+    const CACHEABLE: bool = false;
+
+    fn prefetch(
+        &self,
+        _: &ExecutionContext<Self>,
+        _: &q::SelectionSet,
+    ) -> Result<Option<r::Value>, Vec<QueryExecutionError>> {
+        Ok(None)
+    }
+
+    fn resolve_objects(
+        &self,
+        _: Option<r::Value>,
+        _field: &q::Field,
+        _field_definition: &s::Field,
+        _object_type: ObjectOrInterface<'_>,
+        _arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        Ok(r::Value::Null)
+    }
+
+    fn resolve_object(
+        &self,
+        _: Option<r::Value>,
+        _field: &q::Field,
+        _field_definition: &s::Field,
+        _object_type: ObjectOrInterface<'_>,
+        _arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        Ok(r::Value::Null)
+    }
+
+    fn resolve_mutation(
+        &self,
+        field: &q::Field,
+        _field_definition: &s::Field,
+        arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        if let Some(amount) = arguments.get("amount") {
+            self.amounts.lock().unwrap().push(amount.clone());
+        }
+        let mut calls = self.calls.lock().unwrap();
+        calls.push(field.name.clone());
+        Ok(r::Value::Int(calls.len() as i64 - 1))
+    }
+
+    async fn query_permit(&self) -> tokio::sync::OwnedSemaphorePermit {
+        Arc::new(tokio::sync::Semaphore::new(1))
+            .acquire_owned()
+            .await
+            .unwrap()
+    }
+}
+
+/// Resolver that resolves toplevel fields like `FixedResolver` and counts
+/// list fields through fields with the suffix `Count`. It remembers the
+/// arguments of every count it resolves
+#[derive(Clone)]
+struct CountingResolver {
+    root: r::Value,
+    counted: Arc<Mutex<Vec<(String, Option<r::Value>)>>>,
+}
+
+#[async_trait]
+impl Resolver for CountingResolver {
+    const CACHEABLE: bool = false;
+
+    fn prefetch(
+        &self,
+        _: &ExecutionContext<Self>,
+        _: &q::SelectionSet,
+    ) -> Result<Option<r::Value>, Vec<QueryExecutionError>> {
+        Ok(Some(self.root.clone()))
+    }
+
+    fn resolve_objects(
+        &self,
+        prefetched_objects: Option<r::Value>,
+        _field: &q::Field,
+        _field_definition: &s::Field,
+        _object_type: ObjectOrInterface<'_>,
+        _arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        Ok(prefetched_objects.unwrap_or(r::Value::Null))
+    }
+
+    fn resolve_object(
+        &self,
+        prefetched_object: Option<r::Value>,
+        _field: &q::Field,
+        _field_definition: &s::Field,
+        _object_type: ObjectOrInterface<'_>,
+        _arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        Ok(prefetched_object.unwrap_or(r::Value::Null))
+    }
+
+    fn count_field_suffix(&self) -> Option<&str> {
+        Some("Count")
+    }
+
+    fn resolve_entity_count(
+        &self,
+        _object_type: &s::ObjectType,
+        list_field: &s::Field,
+        arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        self.counted
+            .lock()
+            .unwrap()
+            .push((list_field.name.clone(), arguments.get("first").cloned()));
+        match &self.root {
+            r::Value::Object(root) => match root.get(&list_field.name) {
+                Some(r::Value::List(values)) => Ok(r::Value::Int(values.len() as i64)),
+                _ => Ok(r::Value::Null),
+            },
+            _ => Ok(r::Value::Null),
+        }
+    }
+
+    async fn query_permit(&self) -> tokio::sync::OwnedSemaphorePermit {
+        Arc::new(tokio::sync::Semaphore::new(1))
+            .acquire_owned()
+            .await
+            .unwrap()
+    }
+}
+
+/// Resolver that resolves fields like `FixedResolver`, but takes `delay` to
+/// resolve a list of objects
+#[derive(Clone)]
+struct SlowResolver {
+    root: r::Value,
+    delay: Duration,
+}
+
+#[async_trait]
+impl Resolver for SlowResolver {
+    const CACHEABLE: bool = false;
+
+    fn prefetch(
+        &self,
+        _: &ExecutionContext<Self>,
+        _: &q::SelectionSet,
+    ) -> Result<Option<r::Value>, Vec<QueryExecutionError>> {
+        Ok(Some(self.root.clone()))
+    }
+
+    fn resolve_objects(
+        &self,
+        prefetched_objects: Option<r::Value>,
+        _field: &q::Field,
+        _field_definition: &s::Field,
+        _object_type: ObjectOrInterface<'_>,
+        _arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        std::thread::sleep(self.delay);
+        Ok(prefetched_objects.unwrap_or(r::Value::Null))
+    }
+
+    fn resolve_object(
+        &self,
+        prefetched_object: Option<r::Value>,
+        _field: &q::Field,
+        _field_definition: &s::Field,
+        _object_type: ObjectOrInterface<'_>,
+        _arguments: &HashMap<&str, r::Value>,
+    ) -> Result<r::Value, QueryExecutionError> {
+        Ok(prefetched_object.unwrap_or(r::Value::Null))
+    }
+
+    async fn query_permit(&self) -> tokio::sync::OwnedSemaphorePermit {
+        Arc::new(tokio::sync::Semaphore::new(1))
+            .acquire_owned()
+            .await
+            .unwrap()
+    }
+}
+
+/// A drain that remembers the messages of all log records
+#[derive(Clone, Default)]
+struct MessageDrain {
+    messages: Arc<Mutex<Vec<String>>>,
+}
+
+impl slog::Drain for MessageDrain {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(&self, record: &slog::Record, _: &slog::OwnedKVList) -> Result<(), slog::Never> {
+        self.messages.lock().unwrap().push(record.msg().to_string());
+        Ok(())
+    }
+}
+
+fn test_schema() -> Schema {
+    Schema::parse(
+        "
+        scalar String
+        scalar Int
+        scalar BigInt
+
+        type Cat {
+          name: String!
+          lives: String!
+        }
+
+        type Dog {
+          name: String!
+          breed: String!
+        }
+
+        union Pet = Cat | Dog
+
+        enum Mood {
+          HAPPY
+          GRUMPY
+        }
+
+        type Owner {
+          name: String!
+          cat: Cat!
+        }
+
+        type Query {
+          favorite: Pet
+          pets: [Pet!]!
+          owners: [Owner]
+          ownersCount(first: Int): Int
+          matrix: [[Int]]
+          strictMatrix: [[Int!]!]
+          legacy: Int @deprecated(reason: \"Use matrix instead\")
+          mood: Mood
+          moods: [Mood]
+        }
+
+        type Mutation {
+          increment: Int!
+          reset: Int!
+          transfer(amount: BigInt!): Int!
+        }
+        ",
+        DeploymentHash::new("executionschema").unwrap(),
+    )
+    .unwrap()
+}
+
+/// Run `query` against `schema`, resolving toplevel fields from `root`
+async fn execute(schema: Schema, root: r::Value, query: &str) -> QueryResult {
+    execute_with(schema, FixedResolver { root }, query).await
+}
+
+/// Run `query` against `schema` using `resolver`
+async fn execute_with<R: Resolver>(schema: Schema, resolver: R, query: &str) -> QueryResult {
+    let logger = Logger::root(slog::Discard, o!());
+    execute_logged(schema, resolver, query, logger).await
+}
+
+/// Run `query` against `schema` using `resolver`, logging to `logger`
+async fn execute_logged<R: Resolver>(
+    schema: Schema,
+    resolver: R,
+    query: &str,
+    logger: Logger,
+) -> QueryResult {
+    let query = Query::new(
+        graphql_parser::parse_query(query).unwrap().into_static(),
+        None,
+    );
+    run_query(schema, resolver, query, logger, false, 1).await
+}
+
+/// Run the already parsed `query` against `schema` using `resolver`,
+/// collecting field timings if `trace` is `true` and completing up to
+/// `list_concurrency` list elements at the same time
+async fn run_query<R: Resolver>(
+    schema: Schema,
+    resolver: R,
+    query: Query,
+    logger: Logger,
+    trace: bool,
+    list_concurrency: usize,
+) -> QueryResult {
+    let options = QueryExecutionOptions {
+        resolver,
+        deadline: None,
+        max_first: std::u32::MAX,
+        max_skip: std::u32::MAX,
+        load_manager: LOAD_MANAGER.clone(),
+        trace,
+        list_concurrency,
+        allow_introspection: true,
+        scalar_coercions: Arc::new(ScalarCoercions::default()),
+    };
+    run_query_with_options(schema, query, logger, options).await
+}
+
+/// Run the already parsed `query` against `schema` with the given `options`
+async fn run_query_with_options<R: Resolver>(
+    schema: Schema,
+    query: Query,
+    logger: Logger,
+    options: QueryExecutionOptions<R>,
+) -> QueryResult {
+    let schema = Arc::new(ApiSchema::from_api_schema(schema).unwrap());
+    let result = match PreparedQuery::new(&logger, schema, None, query, None, 100) {
+        Ok(query) => Ok(Arc::try_unwrap(execute_query(query, None, None, options).await).unwrap()),
+        Err(e) => Err(e),
+    };
+    QueryResult::from(result)
+}
+
+#[tokio::test]
+async fn union_fields() {
+    let cat = object! {
+        __typename: "Cat",
+        name: "Garfield",
+        lives: "9",
+    };
+    let dog = object! {
+        __typename: "Dog",
+        name: "Odie",
+        breed: "mixed",
+    };
+    let root = object! {
+        favorite: cat.clone(),
+        pets: vec![dog, cat],
+    };
+
+    let result = execute(
+        test_schema(),
+        root,
+        "
+        query {
+          favorite { __typename ... on Cat { lives } }
+          pets {
+            __typename
+            ... on Cat { name lives }
+            ... on Dog { name breed }
+          }
+        }",
+    )
+    .await
+    .to_result()
+    .unwrap()
+    .unwrap();
+
+    let expected = object! {
+        favorite: object! { __typename: "Cat", lives: "9" },
+        pets: vec![
+            object! { __typename: "Dog", name: "Odie", breed: "mixed" },
+            object! { __typename: "Cat", name: "Garfield", lives: "9" },
+        ],
+    };
+    assert_eq!(expected, result);
+}
+
+#[tokio::test]
+async fn nested_list_fields() {
+    fn matrix(rows: Vec<Vec<Option<i32>>>) -> r::Value {
+        rows.into_iter()
+            .map(|row| r::Value::List(row.into_iter().map(|v| v.into_value()).collect()))
+            .collect::<Vec<_>>()
+            .into_value()
+    }
+
+    let root = object! {
+        matrix: matrix(vec![vec![Some(1), None], vec![]]),
+        strictMatrix: matrix(vec![vec![Some(1), Some(2)], vec![Some(3)]]),
+    };
+    let result = execute(test_schema(), root, "query { matrix strictMatrix }")
+        .await
+        .to_result()
+        .unwrap()
+        .unwrap();
+    let expected = object! {
+        matrix: matrix(vec![vec![Some(1), None], vec![]]),
+        strictMatrix: matrix(vec![vec![Some(1), Some(2)], vec![Some(3)]]),
+    };
+    assert_eq!(expected, result);
+
+    // A null element in a non-null inner list is an error
+    let root = object! {
+        strictMatrix: matrix(vec![vec![Some(1), None]]),
+    };
+    let errors = execute(test_schema(), root, "query { strictMatrix }")
+        .await
+        .to_result()
+        .unwrap_err();
+    match &errors[0] {
+        QueryError::ExecutionError(QueryExecutionError::NonNullError(_, name)) => {
+            assert_eq!("strictMatrix", name)
+        }
+        e => panic!("expected a non-null error but got {:?}", e),
+    }
+}
+
+#[tokio::test]
+async fn null_propagates_to_nullable_parent() {
+    let root = object! {
+        favorite: object! { __typename: "Cat", name: "Garfield", lives: "9" },
+        owners: vec![
+            object! { name: "Jon", cat: object! { name: "Garfield", lives: "9" } },
+            object! { name: "Liz", cat: object! { name: r::Value::Null, lives: "9" } },
+        ],
+    };
+    let result = execute(
+        test_schema(),
+        root,
+        "query { favorite { ... on Cat { name } } owners { name cat { name } } }",
+    )
+    .await;
+
+    // The missing `name` of Liz's cat nulls the cat, and since `cat` is
+    // non-null, also Liz. The list of owners allows nulls and stops the
+    // propagation; the rest of the data is still returned
+    let expected = serde_json::json!({
+        "data": {
+            "favorite": { "name": "Garfield" },
+            "owners": [
+                { "name": "Jon", "cat": { "name": "Garfield" } },
+                null
+            ]
+        },
+        "errors": [
+            {
+                "message": "Null value resolved for non-null field `name`",
+                "locations": [ { "line": 1, "column": 62 } ]
+            }
+        ]
+    });
+    assert_eq!(expected, serde_json::to_value(&result).unwrap());
+}
+
+#[tokio::test]
+async fn list_element_errors() {
+    fn row(values: Vec<r::Value>) -> r::Value {
+        r::Value::List(values)
+    }
+
+    let bad_rows = || {
+        r::Value::List(vec![
+            row(vec![r::Value::Int(1), r::Value::String("x".to_owned())]),
+            row(vec![r::Value::Int(2)]),
+        ])
+    };
+    let root = object! {
+        matrix: bad_rows(),
+        strictMatrix: bad_rows(),
+    };
+    let result = execute(test_schema(), root, "query { matrix strictMatrix }").await;
+
+    // In `matrix`, only the element that can not be coerced becomes null.
+    // The elements of `strictMatrix` can not be null, and the error nulls
+    // the whole inner list, and since that can not be null either, the
+    // nullable `strictMatrix` field
+    let expected = serde_json::json!({
+        "data": {
+            "matrix": [[1, null], [2]],
+            "strictMatrix": null
+        },
+        "errors": [
+            {
+                "message": "Failed to coerce value `\"x\"` of field `matrix` to scalar type `Int`",
+                "locations": [ { "line": 1, "column": 9 } ]
+            },
+            {
+                "message": "Failed to coerce value `\"x\"` of field `strictMatrix` to scalar type `Int`",
+                "locations": [ { "line": 1, "column": 16 } ]
+            }
+        ]
+    });
+    assert_eq!(expected, serde_json::to_value(&result).unwrap());
+}
+
+#[tokio::test]
+async fn unknown_enum_values_are_rejected() {
+    let mood = |name: &str| r::Value::Enum(name.to_owned());
+    let root = object! {
+        mood: mood("BORED"),
+        moods: vec![mood("HAPPY"), mood("BORED")],
+    };
+    let mut result = execute(test_schema(), root, "query { mood moods }").await;
+
+    // Values that are not part of the enum are nulled and reported
+    let errors = std::mem::take(result.errors_mut());
+    let fields: Vec<_> = errors
+        .iter()
+        .map(|e| match e {
+            QueryError::ExecutionError(QueryExecutionError::EnumCoercionError(
+                _,
+                field,
+                value,
+                enum_type,
+                _,
+            )) => {
+                assert_eq!("Mood", enum_type);
+                assert_eq!("BORED", value.to_string());
+                field.as_str()
+            }
+            e => panic!("expected an enum coercion error but got {:?}", e),
+        })
+        .collect();
+    assert_eq!(vec!["mood", "moods"], fields);
+
+    let expected = serde_json::json!({
+        "data": {
+            "mood": null,
+            "moods": ["HAPPY", null]
+        }
+    });
+    assert_eq!(expected, serde_json::to_value(&result).unwrap());
+}
+
+#[tokio::test]
+async fn list_elements_complete_concurrently() {
+    // Every third owner has a cat whose `lives` is not a string. That
+    // nulls the cat and, since `cat` is non-null, the owner
+    let owners: Vec<r::Value> = (0..20)
+        .map(|i| {
+            let lives = if i % 3 == 0 {
+                r::Value::Int(i)
+            } else {
+                r::Value::String(i.to_string())
+            };
+            object! { name: format!("owner{}", i), cat: object! { name: "Garfield", lives: lives } }
+        })
+        .collect();
+    let root = object! { owners: owners };
+
+    let run = |list_concurrency: usize| {
+        let root = root.clone();
+        async move {
+            let query = Query::new(
+                graphql_parser::parse_query("query { owners { name cat { lives } } }")
+                    .unwrap()
+                    .into_static(),
+                None,
+            );
+            let logger = Logger::root(slog::Discard, o!());
+            let resolver = FixedResolver { root };
+            let result = run_query(
+                test_schema(),
+                resolver,
+                query,
+                logger,
+                false,
+                list_concurrency,
+            )
+            .await;
+            serde_json::to_value(&result).unwrap()
+        }
+    };
+
+    let sequential = run(1).await;
+    let owners = sequential["data"]["owners"].as_array().unwrap();
+    assert_eq!(20, owners.len());
+    for (i, owner) in owners.iter().enumerate() {
+        if i % 3 == 0 {
+            assert!(owner.is_null(), "owner{} is null", i);
+        } else {
+            assert_eq!(format!("owner{}", i), owner["name"]);
+        }
+    }
+    // One error per failed owner, in list order
+    let errors = sequential["errors"].as_array().unwrap();
+    assert_eq!(7, errors.len());
+    for (error, i) in errors.iter().zip((0..20).step_by(3)) {
+        let message = error["message"].as_str().unwrap();
+        assert!(message.contains(&format!("`{}`", i)), "{}", message);
+    }
+
+    // Completing the list concurrently gives exactly the same result,
+    // including the order of elements and errors
+    for list_concurrency in &[2, 4, 7, 20, 64] {
+        assert_eq!(sequential, run(*list_concurrency).await);
+    }
+}
+
+#[tokio::test]
+async fn count_fields() {
+    let owner = object! { name: "Jon", cat: object! { name: "Garfield", lives: "9" } };
+    let resolver = CountingResolver {
+        root: object! { owners: vec![owner.clone(), owner] },
+        counted: Arc::new(Mutex::new(Vec::new())),
+    };
+
+    let result = execute_with(
+        test_schema(),
+        resolver.clone(),
+        "query { ownersCount(first: 5) }",
+    )
+    .await
+    .to_result()
+    .unwrap()
+    .unwrap();
+    assert_eq!(object! { ownersCount: 2 }, result);
+    assert_eq!(
+        vec![("owners".to_string(), Some(r::Value::Int(5)))],
+        *resolver.counted.lock().unwrap()
+    );
+
+    // Resolvers that do not count treat count fields like other fields
+    let result = execute(test_schema(), object! {}, "query { ownersCount }")
+        .await
+        .to_result()
+        .unwrap()
+        .unwrap();
+    assert_eq!(object! { ownersCount: r::Value::Null }, result);
+}
+
+#[tokio::test]
+async fn mutation_fields_run_serially() {
+    let resolver = RecordingResolver::default();
+    let result = execute_with(
+        test_schema(),
+        resolver.clone(),
+        "mutation { reset increment again: increment }",
+    )
+    .await
+    .to_result()
+    .unwrap()
+    .unwrap();
+
+    assert_eq!(
+        vec!["reset", "increment", "increment"],
+        *resolver.calls.lock().unwrap()
+    );
+    let expected = object! {
+        reset: 0,
+        increment: 1,
+        again: 2,
+    };
+    assert_eq!(expected, result);
+
+    // Resolvers that do not know about mutations reject them
+    let result = execute(test_schema(), object! {}, "mutation { reset }").await;
+    assert!(result.has_errors());
+}
+
+#[tokio::test]
+async fn big_int_arguments() {
+    async fn transfer(amount: &str, scalars: ScalarCoercions) -> (QueryResult, Vec<r::Value>) {
+        let resolver = RecordingResolver::default();
+        let query = Query::new(
+            graphql_parser::parse_query(&format!("mutation {{ transfer(amount: {}) }}", amount))
+                .unwrap()
+                .into_static(),
+            None,
+        );
+        let options = QueryExecutionOptions {
+            resolver: resolver.clone(),
+            deadline: None,
+            max_first: std::u32::MAX,
+            max_skip: std::u32::MAX,
+            load_manager: LOAD_MANAGER.clone(),
+            trace: false,
+            list_concurrency: 1,
+            allow_introspection: true,
+            scalar_coercions: Arc::new(scalars),
+        };
+        let logger = Logger::root(slog::Discard, o!());
+        let result = run_query_with_options(test_schema(), query, logger, options).await;
+        let amounts = resolver.amounts.lock().unwrap().clone();
+        (result, amounts)
+    }
+
+    // Valid BigInt strings and Ints reach the resolver as strings
+    let big = "123456789012345678901234567890";
+    let (result, amounts) = transfer(&format!("\"{}\"", big), ScalarCoercions::default()).await;
+    assert!(!result.has_errors(), "{:?}", result);
+    assert_eq!(vec![r::Value::String(big.to_string())], amounts);
+
+    let (result, amounts) = transfer("-42", ScalarCoercions::default()).await;
+    assert!(!result.has_errors(), "{:?}", result);
+    assert_eq!(vec![r::Value::String("-42".to_string())], amounts);
+
+    // Malformed values are rejected before the resolver sees them
+    let (result, amounts) = transfer("\"12.5\"", ScalarCoercions::default()).await;
+    assert!(result.has_errors());
+    assert!(amounts.is_empty());
+
+    // A registered coercion replaces the default one
+    fn hex_big_int(value: q::Value) -> Result<r::Value, q::Value> {
+        match value {
+            q::Value::String(s) if s.starts_with("0x") => u64::from_str_radix(&s[2..], 16)
+                .map(|n| r::Value::String(n.to_string()))
+                .map_err(|_| q::Value::String(s)),
+            value => Err(value),
+        }
+    }
+    let mut scalars = ScalarCoercions::default();
+    scalars.register("BigInt", hex_big_int);
+    let (result, amounts) = transfer("\"0x10\"", scalars).await;
+    assert!(!result.has_errors(), "{:?}", result);
+    assert_eq!(vec![r::Value::String("16".to_string())], amounts);
+}
+
+#[tokio::test]
+async fn deprecated_fields_are_logged() {
+    async fn deprecation_warnings(query: &str) -> usize {
+        let drain = MessageDrain::default();
+        let logger = Logger::root(drain.clone(), o!());
+        let resolver = FixedResolver {
+            root: object! { legacy: 1, matrix: r::Value::Null, },
+        };
+        // Use our own deployment so that other tests that query deprecated
+        // fields do not use up the one warning per field
+        let mut schema = test_schema();
+        schema.id = DeploymentHash::new("deprecatedfields").unwrap();
+        let result = execute_logged(schema, resolver, query, logger).await;
+        assert!(!result.has_errors());
+
+        let messages = drain.messages.lock().unwrap();
+        messages
+            .iter()
+            .filter(|msg| msg.as_str() == "Query uses deprecated field")
+            .count()
+    }
+
+    assert_eq!(1, deprecation_warnings("query { legacy }").await);
+    // We already warned about `legacy`
+    assert_eq!(0, deprecation_warnings("query { legacy }").await);
+    assert_eq!(0, deprecation_warnings("query { matrix }").await);
+}
+
+#[tokio::test]
+async fn operation_name_selects_operation() {
+    async fn run(operation_name: Option<&str>) -> QueryResult {
+        let query = Query::new(
+            graphql_parser::parse_query("query a { legacy } query b { matrix }")
+                .unwrap()
+                .into_static(),
+            None,
+        )
+        .with_operation_name(operation_name.map(str::to_owned));
+        let resolver = FixedResolver {
+            root: object! { legacy: 1, matrix: r::Value::Null, },
+        };
+        let logger = Logger::root(slog::Discard, o!());
+        run_query(test_schema(), resolver, query, logger, false, 1).await
+    }
+
+    let result = run(Some("b")).await.to_result().unwrap().unwrap();
+    assert_eq!(object! { matrix: r::Value::Null }, result);
+
+    let errors = run(None).await.to_result().unwrap_err();
+    match &errors[0] {
+        QueryError::ExecutionError(QueryExecutionError::OperationNameRequired) => (),
+        e => panic!("expected OperationNameRequired but got {:?}", e),
+    }
+
+    let errors = run(Some("c")).await.to_result().unwrap_err();
+    match &errors[0] {
+        QueryError::ExecutionError(QueryExecutionError::OperationNotFound(name)) => {
+            assert_eq!("c", name)
+        }
+        e => panic!("expected OperationNotFound but got {:?}", e),
+    }
+}
+
+#[tokio::test]
+async fn tracing_extension() {
+    async fn run(trace: bool) -> QueryResult {
+        let query = Query::new(
+            graphql_parser::parse_query("query { favorite { __typename ... on Cat { name } } }")
+                .unwrap()
+                .into_static(),
+            None,
+        );
+        let resolver = FixedResolver {
+            root: object! {
+                favorite: object! { __typename: "Cat", name: "Garfield", lives: "9" },
+            },
+        };
+        let logger = Logger::root(slog::Discard, o!());
+        run_query(test_schema(), resolver, query, logger, trace, 1).await
+    }
+
+    let result = run(false).await;
+    assert!(!result.has_errors());
+    assert!(result.extensions().is_none());
+
+    let result = run(true).await;
+    assert!(!result.has_errors());
+    let tracing = result
+        .extensions()
+        .and_then(|extensions| extensions.get("tracing"))
+        .expect("traced queries have a tracing extension");
+    let resolvers = match tracing {
+        r::Value::Object(tracing) => match tracing.get("execution") {
+            Some(r::Value::Object(execution)) => execution.get("resolvers"),
+            _ => None,
+        },
+        _ => None,
+    };
+    let fields: Vec<_> = match resolvers {
+        Some(r::Value::List(resolvers)) => resolvers
+            .iter()
+            .map(|resolver| match resolver {
+                r::Value::Object(resolver) => (
+                    resolver.get("parentType").cloned(),
+                    resolver.get("fieldName").cloned(),
+                ),
+                _ => panic!("resolver traces are objects"),
+            })
+            .collect(),
+        _ => panic!("the trace has a list of resolvers"),
+    };
+
+    // Nested fields finish resolving before their parents
+    let expected: Vec<_> = vec![
+        ("Cat", "__typename"),
+        ("Cat", "name"),
+        ("Query", "favorite"),
+    ]
+    .into_iter()
+    .map(|(parent, field)| {
+        (
+            Some(r::Value::String(parent.to_owned())),
+            Some(r::Value::String(field.to_owned())),
+        )
+    })
+    .collect();
+    assert_eq!(expected, fields);
+}
+
+#[tokio::test]
+async fn introspection_can_be_disabled() {
+    async fn run(query: &str) -> QueryResult {
+        let query = Query::new(
+            graphql_parser::parse_query(query).unwrap().into_static(),
+            None,
+        );
+        let options = QueryExecutionOptions {
+            resolver: FixedResolver {
+                root: object! { matrix: r::Value::Null },
+            },
+            deadline: None,
+            max_first: std::u32::MAX,
+            max_skip: std::u32::MAX,
+            load_manager: LOAD_MANAGER.clone(),
+            trace: false,
+            list_concurrency: 1,
+            allow_introspection: false,
+            scalar_coercions: Arc::new(ScalarCoercions::default()),
+        };
+        let logger = Logger::root(slog::Discard, o!());
+        run_query_with_options(test_schema(), query, logger, options).await
+    }
+
+    let result = run("query { matrix }").await.to_result().unwrap().unwrap();
+    assert_eq!(object! { matrix: r::Value::Null }, result);
+
+    for (query, field) in &[
+        ("query { __schema { queryType { name } } }", "__schema"),
+        ("query { matrix __type(name: \"Cat\") { name } }", "__type"),
+        (
+            "query { favorite { ...cat } } fragment cat on Cat { __typename }",
+            "__typename",
+        ),
+    ] {
+        let errors = run(query).await.to_result().unwrap_err();
+        match &errors[..] {
+            [QueryError::ExecutionError(QueryExecutionError::IntrospectionDisabled(_, name))] => {
+                assert_eq!(*field, name.as_str())
+            }
+            e => panic!("expected IntrospectionDisabled but got {:?}", e),
+        }
+    }
+}
+
+#[tokio::test]
+async fn timeout_is_reported_once() {
+    let owner = object! { name: "Jon", cat: object! { name: "Garfield", lives: "9" } };
+    let resolver = SlowResolver {
+        root: object! { owners: vec![owner.clone(), owner.clone(), owner] },
+        delay: Duration::from_millis(300),
+    };
+    let query = Query::new(
+        graphql_parser::parse_query("query { owners { name cat { name } } }")
+            .unwrap()
+            .into_static(),
+        None,
+    );
+    // The deadline passes while the owners are resolved, so that every
+    // owner runs into it
+    let options = QueryExecutionOptions {
+        resolver,
+        deadline: Some(Instant::now() + Duration::from_millis(100)),
+        max_first: std::u32::MAX,
+        max_skip: std::u32::MAX,
+        load_manager: LOAD_MANAGER.clone(),
+        trace: false,
+        list_concurrency: 1,
+        allow_introspection: true,
+        scalar_coercions: Arc::new(ScalarCoercions::default()),
+    };
+    let logger = Logger::root(slog::Discard, o!());
+    let result = run_query_with_options(test_schema(), query, logger, options).await;
+
+    let expected = serde_json::json!({
+        "errors": [ { "message": "Query timed out" } ]
+    });
+    assert_eq!(expected, serde_json::to_value(&result).unwrap());
+}
@@ -11,7 +11,7 @@ use graph::prelude::{
 };
 use graph_graphql::prelude::{
     api_schema, execute_query, ExecutionContext, Query as PreparedQuery, QueryExecutionOptions,
-    Resolver,
+    Resolver, ScalarCoercions,
 };
 use test_store::LOAD_MANAGER;
 
@@ -571,6 +571,10 @@ async fn introspection_query(schema: Schema, query: &str) -> QueryResult {
         max_first: std::u32::MAX,
         max_skip: std::u32::MAX,
         load_manager: LOAD_MANAGER.clone(),
+        trace: false,
+        list_concurrency: 1,
+        allow_introspection: true,
+        scalar_coercions: Arc::new(ScalarCoercions::default()),
     };
 
     let schema = Arc::new(ApiSchema::from_api_schema(schema).unwrap());
@@ -31,9 +31,9 @@ use graph::{
 use graph_graphql::{prelude::*, subscription::execute_subscription};
 use test_store::{
     deployment_state, execute_subgraph_query_with_complexity, execute_subgraph_query_with_deadline,
-    result_size_metrics, revert_block, run_test_sequentially, transact_entity_operations,
-    transact_errors, Store, BLOCK_ONE, GENESIS_PTR, LOAD_MANAGER, LOGGER, METRICS_REGISTRY, STORE,
-    SUBSCRIPTION_MANAGER,
+    result_size_metrics, revert_block, run_test_sequentially, transact_entities_and_errors,
+    transact_entity_operations, transact_errors, Store, BLOCK_ONE, GENESIS_PTR, LOAD_MANAGER,
+    LOGGER, METRICS_REGISTRY, STORE, SUBSCRIPTION_MANAGER,
 };
 
 const NETWORK_NAME: &str = "fake_network";
@@ -1179,6 +1179,50 @@ fn ambiguous_derived_from_result() {
     })
 }
 
+#[test]
+fn invalid_selections_are_reported_upfront() {
+    run_test_sequentially(|store| async move {
+        let deployment = setup(store.as_ref());
+        let query = graphql_parser::parse_query(
+            "
+        {
+          musician {
+            name
+          }
+          musicians(first: 100) {
+            nickname
+            name {
+              length
+            }
+            mainBand
+          }
+        }
+        ",
+        )
+        .expect("invalid test query")
+        .into_static();
+
+        let result = execute_query_document_with_variables(&deployment.hash, query, None).await;
+
+        let mut errors: Vec<_> = result
+            .to_result()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        errors.sort();
+        assert_eq!(
+            vec![
+                "No value provided for required argument: `id`",
+                "Selection set for type `Band` is empty",
+                "Type `Musician` has no field `nickname`",
+                "Type `String` has no field `length`",
+            ],
+            errors
+        );
+    })
+}
+
 #[test]
 fn can_filter_by_relationship_fields() {
     run_test_sequentially(|store| async move {
@@ -1261,13 +1305,10 @@ fn cannot_filter_by_derved_relationship_fields() {
 
         match &result.to_result().unwrap_err()[0] {
             QueryError::ExecutionError(QueryExecutionError::InvalidArgumentError(_, s, v)) => {
-                assert_eq!(s, "where");
+                assert_eq!(s, "where.writtenSongs");
                 assert_eq!(
                     r::Value::try_from(v.clone()).unwrap(),
-                    object_value(vec![(
-                        "writtenSongs",
-                        r::Value::List(vec![r::Value::String(String::from("s1"))])
-                    )]),
+                    r::Value::List(vec![r::Value::String(String::from("s1"))]),
                 );
             }
             e => panic!("expected ResolveEntitiesError, got {}", e),
@@ -1831,3 +1872,82 @@ fn non_fatal_errors() {
         assert_eq!(expected, serde_json::to_value(&result).unwrap());
     })
 }
+
+#[test]
+fn non_fatal_errors_with_field_errors() {
+    use serde_json::json;
+    use test_store::block_store::BLOCK_TWO;
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup_with_features(
+            store.as_ref(),
+            "testNonFatalErrorsWithFieldErrors",
+            BTreeSet::from_iter(Some(SubgraphFeature::NonFatalErrors)),
+        );
+
+        let err = SubgraphError {
+            subgraph_id: deployment.hash.clone(),
+            message: "cow template handler could not moo event transaction".to_string(),
+            block_ptr: Some(BLOCK_TWO.block_ptr()),
+            handler: Some("handleMoo".to_string()),
+            deterministic: true,
+        };
+
+        // Remove the musician that wrote song `s4` so that resolving its
+        // non-null `writtenBy` fails and the song is nulled out
+        let remove = EntityOperation::Remove {
+            key: EntityKey::data(
+                deployment.hash.clone(),
+                "Musician".to_string(),
+                "m3".to_string(),
+            ),
+        };
+        transact_entities_and_errors(
+            &*STORE,
+            &deployment,
+            BLOCK_TWO.block_ptr(),
+            vec![remove],
+            vec![err],
+        )
+        .await
+        .unwrap();
+
+        // With `allow`, both the field error and the indexing error are
+        // reported alongside the partial data
+        let query = "query { song(id: \"s4\", subgraphError: allow) { title writtenBy { name } } }";
+        let query = graphql_parser::parse_query(query).unwrap().into_static();
+        let result = execute_query_document(&deployment.hash, query).await;
+        let expected = json!({
+            "data": {
+                "song": null
+            },
+            "errors": [
+                {
+                    "message": "Null value resolved for non-null field `writtenBy`",
+                    "locations": [ { "line": 1, "column": 54 } ]
+                },
+                {
+                    "message": "indexing_error"
+                }
+            ]
+        });
+        assert_eq!(expected, serde_json::to_value(&result).unwrap());
+
+        // With `deny`, the data is omitted but both errors remain
+        let query = "query { song(id: \"s4\") { title writtenBy { name } } }";
+        let query = graphql_parser::parse_query(query).unwrap().into_static();
+        let result = execute_query_document(&deployment.hash, query).await;
+        let expected = json!({
+            "errors": [
+                {
+                    "message": "Null value resolved for non-null field `writtenBy`",
+                    "locations": [ { "line": 1, "column": 32 } ]
+                },
+                {
+                    "message": "indexing_error"
+                }
+            ]
+        });
+        assert_eq!(expected, serde_json::to_value(&result).unwrap());
+    })
+}
@@ -1,6 +1,6 @@
 use graph::{
     anyhow::Error,
-    blockchain::{block_ingestor::CLEANUP_BLOCKS, BlockchainKind},
+    blockchain::BlockchainKind,
     prelude::{
         anyhow::{anyhow, bail, Context, Result},
         info,
@@ -101,12 +101,6 @@ impl Config {
         if !self.stores.contains_key(PRIMARY_SHARD.as_str()) {
             return Err(anyhow!("missing a primary store"));
         }
-        if self.stores.len() > 1 && *CLEANUP_BLOCKS {
-            // See 8b6ad0c64e244023ac20ced7897fe666
-            return Err(anyhow!(
-                "GRAPH_ETHEREUM_CLEANUP_BLOCKS can not be used with a sharded store"
-            ));
-        }
         for (key, shard) in self.stores.iter_mut() {
             shard.validate(&key)?;
         }
@@ -61,7 +61,18 @@ impl Future for GraphQLRequest {
             )),
         }?;
 
-        Ok(Async::Ready(Query::new(document, variables)))
+        // Parse the "operationName" field of the JSON body, if present
+        let operation_name = match obj.get("operationName") {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(serde_json::Value::String(name)) => Ok(Some(name.clone())),
+            _ => Err(GraphQLServerError::ClientError(
+                "Invalid operation name provided".to_string(),
+            )),
+        }?;
+
+        Ok(Async::Ready(
+            Query::new(document, variables).with_operation_name(operation_name),
+        ))
     }
 }
 
@@ -185,4 +196,28 @@ mod tests {
         assert_eq!(query.document, expected_query);
         assert_eq!(query.variables, Some(expected_variables));
     }
+
+    #[test]
+    fn parses_operation_name() {
+        let request = GraphQLRequest::new(hyper::body::Bytes::from(
+            "\
+                 {\
+                 \"query\": \"query a { user { name } } query b { user { id } }\", \
+                 \"operationName\": \"b\" \
+                 }",
+        ));
+        let query = request.wait().expect("Should accept an operation name");
+        assert_eq!(query.operation_name, Some(String::from("b")));
+
+        let request = GraphQLRequest::new(hyper::body::Bytes::from(
+            "\
+                 {\
+                 \"query\": \"{ user { name } }\", \
+                 \"operationName\": 5 \
+                 }",
+        ));
+        request
+            .wait()
+            .expect_err("Should reject non-string operation names");
+    }
 }
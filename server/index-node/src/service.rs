@@ -9,7 +9,9 @@ use std::task::Poll;
 
 use graph::{components::server::query::GraphQLServerError, data::query::QueryResults};
 use graph::{components::store::StatusStore, prelude::*};
-use graph_graphql::prelude::{execute_query, Query as PreparedQuery, QueryExecutionOptions};
+use graph_graphql::prelude::{
+    execute_query, Query as PreparedQuery, QueryExecutionOptions, ScalarCoercions,
+};
 
 use crate::explorer::Explorer;
 use crate::request::IndexNodeRequest;
@@ -135,6 +137,10 @@ where
                 max_first: std::u32::MAX,
                 max_skip: std::u32::MAX,
                 load_manager,
+                trace: false,
+                list_concurrency: 1,
+                allow_introspection: true,
+                scalar_coercions: Arc::new(ScalarCoercions::default()),
             };
             let result = execute_query(query_clone.cheap_clone(), None, None, options).await;
             query_clone.log_execution(0);
@@ -317,7 +317,8 @@ where
                     let subscription = Subscription {
                         // Subscriptions currently do not benefit from the generational cache
                         // anyways, so don't bother passing a network.
-                        query: Query::new(query, variables),
+                        query: Query::new(query, variables)
+                            .with_operation_name(payload.operation_name),
                     };
 
                     debug!(logger, "Start operation";
@@ -0,0 +1,3 @@
+alter table ethereum_networks
+    drop column ingest_cursor_hash,
+    drop column ingest_cursor_number;
@@ -0,0 +1,3 @@
+alter table ethereum_networks
+    add column ingest_cursor_hash varchar default null,
+    add column ingest_cursor_number bigint default null;
@@ -0,0 +1,11 @@
+do $$
+declare
+    nsp varchar;
+begin
+    for nsp in
+        select namespace from ethereum_networks where namespace != 'public'
+    loop
+        execute format('drop index if exists %I.blocks_parent_hash', nsp);
+    end loop;
+end;
+$$;
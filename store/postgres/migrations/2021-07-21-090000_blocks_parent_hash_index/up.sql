@@ -0,0 +1,13 @@
+-- Chains created from now on get this index when their schema is
+-- created; add it to the schemas of existing chains
+do $$
+declare
+    nsp varchar;
+begin
+    for nsp in
+        select namespace from ethereum_networks where namespace != 'public'
+    loop
+        execute format('create index if not exists blocks_parent_hash on %I.blocks using btree(parent_hash)', nsp);
+    end loop;
+end;
+$$;
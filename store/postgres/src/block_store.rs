@@ -1,6 +1,7 @@
 use std::{
     collections::{HashMap, HashSet},
     iter::FromIterator,
+    str::FromStr,
     sync::{Arc, RwLock},
     time::Duration,
 };
@@ -24,6 +25,21 @@ use crate::{
     primary::Mirror as PrimaryMirror, ChainStore, NotificationSender, Shard, PRIMARY_SHARD,
 };
 
+lazy_static! {
+    /// How long chain stores, and lookups of chain heads across shards,
+    /// wait for a database connection before giving up. Set by
+    /// `GRAPH_CHAIN_STORE_CONNECTION_TIMEOUT` in milliseconds
+    static ref CHAIN_STORE_CONNECTION_TIMEOUT: Duration = Duration::from_millis(
+        std::env::var("GRAPH_CHAIN_STORE_CONNECTION_TIMEOUT")
+            .ok()
+            .map(|s| u64::from_str(&s).unwrap_or_else(|_| panic!(
+                "GRAPH_CHAIN_STORE_CONNECTION_TIMEOUT must be a positive number, but is `{}`",
+                s
+            )))
+            .unwrap_or(5000)
+    );
+}
+
 #[cfg(debug_assertions)]
 pub const FAKE_NETWORK_SHARED: &str = "fake_network_shared";
 
@@ -368,6 +384,9 @@ impl BlockStore {
             status,
             sender,
             pool,
+            self.pools.clone(),
+            *CHAIN_STORE_CONNECTION_TIMEOUT,
+            None,
         );
         if create {
             store.create(&ident)?;
@@ -389,7 +408,7 @@ impl BlockStore {
             let cached = match self.chain_head_cache.get(shard.as_str()) {
                 Some(cached) => cached,
                 None => {
-                    let conn = match pool.get() {
+                    let conn = match pool.get_timeout(*CHAIN_STORE_CONNECTION_TIMEOUT) {
                         Ok(conn) => conn,
                         Err(StoreError::DatabaseUnavailable) => continue,
                         Err(e) => return Err(e),
@@ -408,6 +427,26 @@ impl BlockStore {
         Ok(map)
     }
 
+    /// Return a map from network name to the network's chain head pointer
+    /// for the networks in `names`. Networks that we do not know about or
+    /// that do not have a chain head yet are not included in the map.
+    /// Unlike `chain_head_pointers`, the information is not cached
+    pub fn chain_head_pointers_for(
+        &self,
+        names: &[&str],
+    ) -> Result<HashMap<String, BlockPtr>, StoreError> {
+        let mut map = HashMap::new();
+        for pool in self.pools.values() {
+            let conn = match pool.get_timeout(*CHAIN_STORE_CONNECTION_TIMEOUT) {
+                Ok(conn) => conn,
+                Err(StoreError::DatabaseUnavailable) => continue,
+                Err(e) => return Err(e),
+            };
+            map.extend(ChainStore::chain_head_pointers_for(&conn, names)?);
+        }
+        Ok(map)
+    }
+
     pub fn chain_head_block(&self, chain: &str) -> Result<Option<BlockNumber>, StoreError> {
         let store = self
             .store(chain)
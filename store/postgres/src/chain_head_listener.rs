@@ -87,6 +87,44 @@ struct ChainHeadUpdate {
     pub network_name: String,
     pub head_block_hash: String,
     pub head_block_number: u64,
+    /// The chain head before this update. This is not set if the chain did
+    /// not have a head before, for updates that just repeat the current
+    /// head, and by older versions of graph-node
+    #[serde(default)]
+    pub previous_head_block_hash: Option<String>,
+    #[serde(default)]
+    pub previous_head_block_number: Option<u64>,
+}
+
+impl ChainHeadUpdate {
+    /// Return `true` if the chain head did not advance, which means that
+    /// the chain was reorganized
+    fn is_reorg(&self) -> bool {
+        match self.previous_head_block_number {
+            Some(previous) => self.head_block_number <= previous,
+            None => false,
+        }
+    }
+}
+
+/// The payload of the notification that tells listeners that the head of
+/// `network_name` is now `head`, and was `previous` before that
+fn chain_head_update_message(
+    network_name: &str,
+    head: (&str, i64),
+    previous: Option<(&str, i64)>,
+) -> serde_json::Value {
+    let (hash, number) = head;
+    let mut msg = json! ({
+        "network_name": network_name,
+        "head_block_hash": hash,
+        "head_block_number": number
+    });
+    if let Some((previous_hash, previous_number)) = previous {
+        msg["previous_head_block_hash"] = json!(previous_hash);
+        msg["previous_head_block_number"] = json!(previous_number);
+    }
+    msg
 }
 
 pub struct ChainHeadUpdateListener {
@@ -97,6 +135,7 @@ pub struct ChainHeadUpdateListener {
 
 /// Sender for messages that the `ChainHeadUpdateListener` on other nodes
 /// will receive. The sender is specific to a particular chain.
+#[derive(Clone)]
 pub(crate) struct ChainHeadUpdateSender {
     pool: ConnectionPool,
     chain_name: String,
@@ -176,6 +215,13 @@ impl ChainHeadUpdateListener {
                 metrics
                     .set_chain_head_number(&update.network_name, *&update.head_block_number as i64);
 
+                if update.is_reorg() {
+                    debug!(logger, "chain head moved back";
+                        "network" => &update.network_name,
+                        "from" => update.previous_head_block_number,
+                        "to" => update.head_block_number);
+                }
+
                 // If there are subscriptions for this network, notify them.
                 if let Some(watcher) = watchers.read(&logger).get(&update.network_name) {
                     // Due to a tokio bug, we must assume that the watcher can deadlock, see
@@ -273,15 +319,82 @@ impl ChainHeadUpdateSender {
         }
     }
 
-    pub fn send(&self, hash: &str, number: i64) -> Result<(), StoreError> {
-        let msg = json! ({
-            "network_name": &self.chain_name,
-            "head_block_hash": hash,
-            "head_block_number": number
-        });
+    /// Tell listeners that the chain head is now the block with `hash`
+    /// and `number`. If the chain head changed, `previous` is the head
+    /// before the change so that listeners can detect reorgs
+    pub fn send(
+        &self,
+        hash: &str,
+        number: i64,
+        previous: Option<(&str, i64)>,
+    ) -> Result<(), StoreError> {
+        let msg = chain_head_update_message(&self.chain_name, (hash, number), previous);
 
         let conn = self.pool.get()?;
         self.sender
             .notify(&conn, CHANNEL_NAME.as_str(), Some(&self.chain_name), &msg)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "7347afe69254df06729e123610b00b8b11f15cfae3241f9366fb113aec07489c";
+    const OTHER_HASH: &str = "f8ccbd3877eb98c958614f395dd351211afb9abba187bfc1fb4ac414b099c4a6";
+
+    fn parse(msg: serde_json::Value) -> ChainHeadUpdate {
+        serde_json::from_value(msg).expect("message is a valid chain head update")
+    }
+
+    #[test]
+    fn chain_head_update_carries_previous_head() {
+        // The chain advances
+        let update = parse(chain_head_update_message(
+            "mainnet",
+            (HASH, 3),
+            Some((OTHER_HASH, 2)),
+        ));
+        assert_eq!(
+            Some(OTHER_HASH.to_string()),
+            update.previous_head_block_hash
+        );
+        assert_eq!(Some(2), update.previous_head_block_number);
+        assert!(!update.is_reorg());
+
+        // A reorg moves the head back to a lower block number
+        let update = parse(chain_head_update_message(
+            "mainnet",
+            (OTHER_HASH, 2),
+            Some((HASH, 3)),
+        ));
+        assert_eq!(2, update.head_block_number);
+        assert_eq!(Some(3), update.previous_head_block_number);
+        assert!(update.is_reorg());
+
+        // A reorg that replaces the head with a sibling
+        let update = parse(chain_head_update_message(
+            "mainnet",
+            (OTHER_HASH, 3),
+            Some((HASH, 3)),
+        ));
+        assert!(update.is_reorg());
+    }
+
+    #[test]
+    fn chain_head_update_without_previous_head() {
+        let update = parse(chain_head_update_message("mainnet", (HASH, 3), None));
+        assert_eq!(None, update.previous_head_block_hash);
+        assert_eq!(None, update.previous_head_block_number);
+        assert!(!update.is_reorg());
+
+        // Messages from nodes that do not send the previous head
+        let update = parse(json!({
+            "network_name": "mainnet",
+            "head_block_hash": HASH,
+            "head_block_number": 3
+        }));
+        assert_eq!(None, update.previous_head_block_number);
+        assert!(!update.is_reorg());
+    }
+}
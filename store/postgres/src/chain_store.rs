@@ -3,7 +3,7 @@ use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::sql_types::Text;
 use diesel::{insert_into, update};
-use graph::blockchain::{Block, ChainIdentifier};
+use graph::blockchain::{Block, BlockHash, ChainIdentifier};
 use graph::prelude::web3::types::H256;
 use graph::{
     constraint_violation,
@@ -14,20 +14,26 @@ use graph::{
 };
 
 use graph::ensure;
+use graph::prelude::{anyhow::anyhow, hex, tokio};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     convert::{TryFrom, TryInto},
     iter::FromIterator,
-    sync::Arc,
+    ops::Range,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
 };
 
 use graph::prelude::{
     serde_json as json, transaction_receipt::LightTransactionReceipt, BlockNumber, BlockPtr, Error,
+    EthereumBlock, LightEthereumBlock,
 };
 
 use crate::{
-    block_store::ChainStatus, chain_head_listener::ChainHeadUpdateSender,
-    connection_pool::ConnectionPool,
+    block_store::ChainStatus,
+    chain_head_listener::ChainHeadUpdateSender,
+    connection_pool::{ConnectionPool, ForeignServer},
+    Shard, PRIMARY_SHARD,
 };
 
 /// Tables in the 'public' database schema that store chain-specific data
@@ -39,11 +45,14 @@ mod public {
             head_block_hash -> Nullable<Varchar>,
             head_block_number -> Nullable<BigInt>,
             net_version -> Varchar,
+            ingest_cursor_hash -> Nullable<Varchar>,
+            ingest_cursor_number -> Nullable<BigInt>,
             genesis_block_hash -> Varchar,
         }
     }
 }
 
+use data::normalize_block_hash;
 pub use data::Storage;
 
 /// Encapuslate access to the blocks table for a chain.
@@ -60,7 +69,7 @@ mod data {
         types::{FromSql, ToSql},
     };
     use diesel::{
-        sql_types::{BigInt, Bytea, Integer, Jsonb},
+        sql_types::{Array, BigInt, Bool, Bytea, Integer, Jsonb, Nullable},
         update,
     };
     use diesel_dynamic_schema as dds;
@@ -70,8 +79,10 @@ mod data {
         prelude::{transaction_receipt::LightTransactionReceipt, StoreError},
     };
 
+    use std::collections::HashSet;
     use std::fmt;
     use std::iter::FromIterator;
+    use std::ops::Range;
     use std::{convert::TryFrom, io::Write};
 
     use graph::prelude::{serde_json as json, web3::types::H256, BlockNumber, BlockPtr, Error};
@@ -130,6 +141,47 @@ mod data {
         hash: Vec<u8>,
     }
 
+    /// The query that finds the hash of the ancestor at offset `$2` of the
+    /// block with hash `$1` by following parent hashes in `blocks`
+    fn ancestor_sql(blocks: impl fmt::Display) -> String {
+        format!(
+            "
+        with recursive ancestors(block_hash, block_offset) as (
+            values ($1, 0)
+            union all
+            select b.parent_hash, a.block_offset+1
+              from ancestors a, {} b
+             where a.block_hash = b.hash
+               and a.block_offset < $2
+        )
+        select a.block_hash as hash
+          from ancestors a
+         where a.block_offset = $2;",
+            blocks
+        )
+    }
+
+    /// Parse a block hash given as a hex string. The string may or may not
+    /// have a `0x` prefix and may use upper- or lowercase hex digits, but
+    /// it must encode exactly 32 bytes
+    pub(super) fn normalize_block_hash(input: &str) -> Result<H256, StoreError> {
+        let hex = input
+            .strip_prefix("0x")
+            .or_else(|| input.strip_prefix("0X"))
+            .unwrap_or(input);
+        if hex.len() != 2 * H256::len_bytes() {
+            return Err(constraint_violation!(
+                "invalid block hash `{}`: expected {} hex digits but got {}",
+                input,
+                2 * H256::len_bytes(),
+                hex.len()
+            ));
+        }
+        let bytes = graph::prelude::hex::decode(hex)
+            .map_err(|e| constraint_violation!("invalid block hash `{}`: {}", input, e))?;
+        Ok(H256::from_slice(&bytes))
+    }
+
     // Like H256::from_slice, but returns an error instead of panicking
     // when `bytes` does not have the right length
     fn h256_from_bytes(bytes: &[u8]) -> Result<H256, StoreError> {
@@ -180,6 +232,10 @@ mod data {
             self.table.column::<BigInt, _>("number")
         }
 
+        fn parent_hash(&self) -> DynColumn<Bytea> {
+            self.table.column::<Bytea, _>("parent_hash")
+        }
+
         fn data(&self) -> DynColumn<Jsonb> {
             self.table.column::<Jsonb, _>("data")
         }
@@ -266,6 +322,18 @@ mod data {
         }
     }
 
+    /// The parent hash of `block`, or the hash indicating 'no parent' if
+    /// it does not have one. It seems to be customary at least on
+    /// EVM-compatible chains to fill the parent hash of the genesis block
+    /// with that value
+    fn parent_hash_or_no_parent(block: &dyn Block) -> BlockHash {
+        const NO_PARENT: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+        block
+            .parent_hash()
+            .unwrap_or_else(|| BlockHash::try_from(NO_PARENT).expect("NO_PARENT is a valid hash"))
+    }
+
     #[derive(Clone, Debug, AsExpression, FromSqlRow)]
     #[sql_type = "diesel::sql_types::Text"]
     /// Storage for a chain. The underlying namespace (database schema) is either
@@ -309,18 +377,37 @@ mod data {
                 return Ok(Self::Shared);
             }
 
-            if !s.starts_with(Self::PREFIX) || s.len() <= Self::PREFIX.len() {
+            if !Self::is_private_namespace(&s) {
                 return Err(s);
             }
-            for c in s.chars().skip(Self::PREFIX.len()) {
-                if !c.is_numeric() {
-                    return Err(s);
-                }
-            }
 
             Ok(Self::Private(Schema::new(s)))
         }
 
+        /// Check that `s` is of the form `chain[0-9]+`
+        fn is_private_namespace(s: &str) -> bool {
+            s.starts_with(Self::PREFIX)
+                && s.len() > Self::PREFIX.len()
+                && s.chars()
+                    .skip(Self::PREFIX.len())
+                    .all(|c| c.is_ascii_digit())
+        }
+
+        /// Create the storage for a chain that uses its own namespace
+        /// `namespace`, which must be of the form `chain[0-9]+`. This does
+        /// not touch the database; use `create` to actually set up the
+        /// namespace
+        pub fn private(namespace: &str) -> Result<Self, StoreError> {
+            if !Self::is_private_namespace(namespace) {
+                return Err(StoreError::InvalidIdentifier(format!(
+                    "chain namespace `{}` is not of the form `{}[0-9]+`",
+                    namespace,
+                    Self::PREFIX
+                )));
+            }
+            Ok(Self::Private(Schema::new(namespace.to_string())))
+        }
+
         /// Create dedicated database tables for this chain if it uses
         /// `Storage::Private`. If it uses `Storage::Shared`, do nothing since
         /// a regular migration will already have created the `ethereum_blocks`
@@ -337,6 +424,7 @@ mod data {
                   data         jsonb not null
                 );
                 create index blocks_number ON {nsp}.blocks using btree(number);
+                create index blocks_parent_hash ON {nsp}.blocks using btree(parent_hash);
 
                 create table {nsp}.call_cache (
 	              id               bytea not null primary key,
@@ -390,6 +478,72 @@ mod data {
             }
         }
 
+        /// Check that all the tables we need for `Storage::Private` exist
+        /// and return an error that lists the missing ones if they do not.
+        /// The tables for `Storage::Shared` are created by migrations and
+        /// are not checked
+        pub(super) fn check_storage(&self, conn: &PgConnection) -> Result<(), StoreError> {
+            #[derive(QueryableByName)]
+            struct TableName {
+                #[sql_type = "Text"]
+                table_name: String,
+            }
+
+            match self {
+                Storage::Shared => Ok(()),
+                Storage::Private(Schema { name, .. }) => {
+                    let present: HashSet<String> = sql_query(
+                        "select table_name::text from information_schema.tables \
+                          where table_schema = $1",
+                    )
+                    .bind::<Text, _>(name)
+                    .load::<TableName>(conn)?
+                    .into_iter()
+                    .map(|table| table.table_name)
+                    .collect();
+
+                    let missing: Vec<_> = vec![
+                        BlocksTable::TABLE_NAME,
+                        CallCacheTable::TABLE_NAME,
+                        CallMetaTable::TABLE_NAME,
+                    ]
+                    .into_iter()
+                    .filter(|table| !present.contains(*table))
+                    .collect();
+
+                    if missing.is_empty() {
+                        Ok(())
+                    } else {
+                        Err(constraint_violation!(
+                            "the schema {} for the chain store is missing the tables {}",
+                            name,
+                            missing.join(", ")
+                        ))
+                    }
+                }
+            }
+        }
+
+        /// Rename `table` in the schema of `Storage::Private` to `new_name`
+        #[cfg(debug_assertions)]
+        pub(super) fn rename_table(
+            &self,
+            conn: &PgConnection,
+            table: &str,
+            new_name: &str,
+        ) -> Result<(), StoreError> {
+            match self {
+                Storage::Shared => Ok(()),
+                Storage::Private(Schema { name, .. }) => {
+                    conn.batch_execute(&format!(
+                        "alter table {}.{} rename to {}",
+                        name, table, new_name
+                    ))?;
+                    Ok(())
+                }
+            }
+        }
+
         pub(super) fn truncate_block_cache(&self, conn: &PgConnection) -> Result<(), StoreError> {
             let table_name = match &self {
                 Storage::Shared => ETHEREUM_BLOCKS_TABLE_NAME,
@@ -410,18 +564,10 @@ mod data {
             block: &dyn Block,
             overwrite: bool,
         ) -> Result<(), StoreError> {
-            // Hash indicating 'no parent'. It seems to be customary at
-            // least on EVM-compatible chains to fill the parent hash of the
-            // genesis block with this value
-            const NO_PARENT: &str =
-                "0000000000000000000000000000000000000000000000000000000000000000";
-
             let number = block.number() as i64;
             let data = block.data().expect("Failed to serialize block");
             let hash = block.hash();
-            let parent_hash = block.parent_hash().unwrap_or_else(|| {
-                BlockHash::try_from(NO_PARENT).expect("NO_PARENT is a valid hash")
-            });
+            let parent_hash = parent_hash_or_no_parent(block);
 
             match self {
                 Storage::Shared => {
@@ -478,6 +624,110 @@ mod data {
             Ok(())
         }
 
+        /// Insert all `blocks` with as few statements as possible. Blocks
+        /// that are already in the store are overwritten if `overwrite` is
+        /// `true` and left alone otherwise
+        pub(super) fn upsert_blocks(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            blocks: &[&dyn Block],
+            overwrite: bool,
+        ) -> Result<(), StoreError> {
+            // Postgres refuses to touch the same row twice in one
+            // `insert .. on conflict` statement, so only keep the last
+            // occurrence of each block
+            let mut seen = HashSet::new();
+            let mut rows = Vec::with_capacity(blocks.len());
+            for block in blocks.iter().rev() {
+                let hash = block.hash();
+                if seen.insert(hash.clone()) {
+                    rows.push((
+                        hash,
+                        block.number() as i64,
+                        parent_hash_or_no_parent(*block),
+                        block.data().expect("Failed to serialize block"),
+                    ));
+                }
+            }
+            if rows.is_empty() {
+                return Ok(());
+            }
+
+            match self {
+                Storage::Shared => {
+                    use diesel::upsert::excluded;
+                    use public::ethereum_blocks as b;
+
+                    let values: Vec<_> = rows
+                        .into_iter()
+                        .map(|(hash, number, parent_hash, data)| {
+                            (
+                                b::hash.eq(hash.hash_hex()),
+                                b::number.eq(number),
+                                b::parent_hash.eq(parent_hash.hash_hex()),
+                                b::network_name.eq(chain),
+                                b::data.eq(data),
+                            )
+                        })
+                        .collect();
+
+                    if overwrite {
+                        insert_into(b::table)
+                            .values(values)
+                            .on_conflict(b::hash)
+                            .do_update()
+                            .set((
+                                b::number.eq(excluded(b::number)),
+                                b::parent_hash.eq(excluded(b::parent_hash)),
+                                b::network_name.eq(excluded(b::network_name)),
+                                b::data.eq(excluded(b::data)),
+                            ))
+                            .execute(conn)?;
+                    } else {
+                        insert_into(b::table)
+                            .values(values)
+                            .on_conflict(b::hash)
+                            .do_nothing()
+                            .execute(conn)?;
+                    }
+                }
+                Storage::Private(Schema { blocks, .. }) => {
+                    let conflict = if overwrite {
+                        "do update set number = excluded.number, \
+                                       parent_hash = excluded.parent_hash, \
+                                       data = excluded.data"
+                    } else {
+                        "do nothing"
+                    };
+                    let query = format!(
+                        "insert into {}(hash, number, parent_hash, data) \
+                         select * from unnest($1::bytea[], $2::int8[], $3::bytea[], $4::jsonb[]) \
+                             on conflict(hash) {}",
+                        blocks.qname, conflict
+                    );
+
+                    let mut hashes = Vec::with_capacity(rows.len());
+                    let mut numbers = Vec::with_capacity(rows.len());
+                    let mut parent_hashes = Vec::with_capacity(rows.len());
+                    let mut datas = Vec::with_capacity(rows.len());
+                    for (hash, number, parent_hash, data) in rows {
+                        hashes.push(hash.as_slice().to_vec());
+                        numbers.push(number);
+                        parent_hashes.push(parent_hash.as_slice().to_vec());
+                        datas.push(data);
+                    }
+                    sql_query(query)
+                        .bind::<Array<Bytea>, _>(hashes)
+                        .bind::<Array<BigInt>, _>(numbers)
+                        .bind::<Array<Bytea>, _>(parent_hashes)
+                        .bind::<Array<Jsonb>, _>(datas)
+                        .execute(conn)?;
+                }
+            };
+            Ok(())
+        }
+
         pub(super) fn blocks(
             &self,
             conn: &PgConnection,
@@ -518,6 +768,158 @@ mod data {
             .map_err(Into::into)
         }
 
+        /// Like `blocks`, but return the complete `data` for each block,
+        /// i.e., the block together with its transaction receipts. Entries
+        /// that only contain the block are wrapped so that they look like
+        /// a complete block without any receipts
+        pub(super) fn full_blocks(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            hashes: &[H256],
+        ) -> Result<Vec<json::Value>, Error> {
+            use diesel::dsl::any;
+
+            // see also 7736e440-4c6b-11ec-8c4d-b42e99f52061
+            const FULL_DATA: &str = "case when data ? 'block' then data \
+                 else jsonb_build_object('block', data, 'transaction_receipts', '[]'::jsonb) end";
+
+            match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    b::table
+                        .select(sql::<Jsonb>(FULL_DATA))
+                        .filter(b::network_name.eq(chain))
+                        .filter(b::hash.eq(any(Vec::from_iter(
+                            hashes.into_iter().map(|h| format!("{:x}", h)),
+                        ))))
+                        .load::<json::Value>(conn)
+                }
+                Storage::Private(Schema { blocks, .. }) => blocks
+                    .table()
+                    .select(sql::<Jsonb>(FULL_DATA))
+                    .filter(
+                        blocks
+                            .hash()
+                            .eq(any(Vec::from_iter(hashes.iter().map(|h| h.as_bytes())))),
+                    )
+                    .load::<json::Value>(conn),
+            }
+            .map_err(Into::into)
+        }
+
+        /// Return at most `limit` blocks with numbers in `range`, ordered
+        /// by number and hash. Only blocks that come strictly after the
+        /// `(number, hash)` pair `after` in that order are returned, which
+        /// makes it possible to page through a range of blocks
+        pub(super) fn blocks_in_range(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            range: &Range<BlockNumber>,
+            after: Option<&(BlockNumber, H256)>,
+            limit: usize,
+        ) -> Result<Vec<(BlockNumber, H256, json::Value)>, Error> {
+            // Starting right before the range with the smallest possible
+            // hash is the same as not having a cursor at all
+            let (after_number, after_hash) = match after {
+                Some((number, hash)) => (*number as i64, *hash),
+                None => (range.start as i64 - 1, H256::zero()),
+            };
+            let start = range.start as i64;
+            let end = range.end as i64;
+            let limit = limit as i64;
+
+            // See `blocks` for why we look at `data -> 'block'`
+            match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    let after_hash = format!("{:x}", after_hash);
+                    b::table
+                        .select((
+                            b::number,
+                            b::hash,
+                            sql::<Jsonb>("coalesce(data -> 'block', data)"),
+                        ))
+                        .filter(b::network_name.eq(chain))
+                        .filter(b::number.ge(start))
+                        .filter(b::number.lt(end))
+                        .filter(
+                            b::number
+                                .gt(after_number)
+                                .or(b::number.eq(after_number).and(b::hash.gt(after_hash))),
+                        )
+                        .order_by((b::number, b::hash))
+                        .limit(limit)
+                        .load::<(i64, String, json::Value)>(conn)?
+                        .into_iter()
+                        .map(|(number, hash, data)| {
+                            Ok((number as BlockNumber, normalize_block_hash(&hash)?, data))
+                        })
+                        .collect()
+                }
+                Storage::Private(Schema { blocks, .. }) => blocks
+                    .table()
+                    .select((
+                        blocks.number(),
+                        blocks.hash(),
+                        sql::<Jsonb>("coalesce(data -> 'block', data)"),
+                    ))
+                    .filter(blocks.number().ge(start))
+                    .filter(blocks.number().lt(end))
+                    .filter(
+                        blocks.number().gt(after_number).or(blocks
+                            .number()
+                            .eq(after_number)
+                            .and(blocks.hash().gt(after_hash.as_bytes()))),
+                    )
+                    .order_by((blocks.number(), blocks.hash()))
+                    .limit(limit)
+                    .load::<(i64, Vec<u8>, json::Value)>(conn)?
+                    .into_iter()
+                    .map(|(number, hash, data)| {
+                        Ok((number as BlockNumber, h256_from_bytes(&hash)?, data))
+                    })
+                    .collect(),
+            }
+        }
+
+        /// Return the data of the `limit` blocks with the highest numbers,
+        /// ordered by number descending and then by hash. This does not
+        /// look at the chain head, and can therefore include blocks that
+        /// are not on the canonical chain
+        pub(super) fn latest_blocks(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            limit: usize,
+        ) -> Result<Vec<json::Value>, Error> {
+            let limit = limit as i64;
+
+            // See `blocks` for why we look at `data -> 'block'`
+            match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    b::table
+                        .select(sql::<Jsonb>("coalesce(data -> 'block', data)"))
+                        .filter(b::network_name.eq(chain))
+                        .order_by((b::number.desc(), b::hash))
+                        .limit(limit)
+                        .load::<json::Value>(conn)
+                }
+                Storage::Private(Schema { blocks, .. }) => blocks
+                    .table()
+                    .select(sql::<Jsonb>("coalesce(data -> 'block', data)"))
+                    .order_by((blocks.number().desc(), blocks.hash()))
+                    .limit(limit)
+                    .load::<json::Value>(conn),
+            }
+            .map_err(Into::into)
+        }
+
         pub(super) fn block_hashes_by_block_number(
             &self,
             conn: &PgConnection,
@@ -534,7 +936,7 @@ mod data {
                         .filter(b::number.eq(number as i64))
                         .get_results::<String>(conn)?
                         .into_iter()
-                        .map(|h| h.parse())
+                        .map(|h| normalize_block_hash(&h))
                         .collect::<Result<Vec<H256>, _>>()
                         .map_err(Error::from)
                 }
@@ -550,6 +952,75 @@ mod data {
             }
         }
 
+        /// Return pointers to all blocks whose number is one of `numbers`,
+        /// ordered by number and hash
+        pub(super) fn block_ptrs_by_numbers(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            numbers: &[BlockNumber],
+        ) -> Result<Vec<BlockPtr>, Error> {
+            let numbers: Vec<i64> = numbers.iter().map(|number| *number as i64).collect();
+            match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    b::table
+                        .select((b::hash, b::number))
+                        .filter(b::network_name.eq(&chain))
+                        .filter(b::number.eq_any(numbers))
+                        .order_by((b::number, b::hash))
+                        .get_results::<(String, i64)>(conn)?
+                        .into_iter()
+                        .map(|(hash, number)| BlockPtr::try_from((hash.as_str(), number)))
+                        .collect()
+                }
+                Storage::Private(Schema { blocks, .. }) => blocks
+                    .table()
+                    .select((blocks.hash(), blocks.number()))
+                    .filter(blocks.number().eq_any(numbers))
+                    .order_by((blocks.number(), blocks.hash()))
+                    .get_results::<(Vec<u8>, i64)>(conn)?
+                    .into_iter()
+                    .map(|(hash, number)| BlockPtr::try_from((hash.as_slice(), number)))
+                    .collect(),
+            }
+        }
+
+        /// Return pointers to all blocks whose parent is `parent_hash`,
+        /// ordered by hash
+        pub(super) fn children_of(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            parent_hash: &H256,
+        ) -> Result<Vec<BlockPtr>, Error> {
+            match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    b::table
+                        .select((b::hash, b::number))
+                        .filter(b::network_name.eq(&chain))
+                        .filter(b::parent_hash.eq(format!("{:x}", parent_hash)))
+                        .order_by(b::hash)
+                        .get_results::<(String, i64)>(conn)?
+                        .into_iter()
+                        .map(|(hash, number)| BlockPtr::try_from((hash.as_str(), number)))
+                        .collect()
+                }
+                Storage::Private(Schema { blocks, .. }) => blocks
+                    .table()
+                    .select((blocks.hash(), blocks.number()))
+                    .filter(blocks.parent_hash().eq(parent_hash.as_bytes()))
+                    .order_by(blocks.hash())
+                    .get_results::<(Vec<u8>, i64)>(conn)?
+                    .into_iter()
+                    .map(|(hash, number)| BlockPtr::try_from((hash.as_slice(), number)))
+                    .collect(),
+            }
+        }
+
         pub(super) fn confirm_block_hash(
             &self,
             conn: &PgConnection,
@@ -557,6 +1028,19 @@ mod data {
             number: BlockNumber,
             hash: &H256,
         ) -> Result<usize, Error> {
+            self.confirm_block_hash_reporting(conn, chain, number, hash)
+                .map(|removed| removed.len())
+        }
+
+        /// Delete all blocks with the given `number` other than the one
+        /// with `hash` and return the hashes of the deleted blocks
+        pub(super) fn confirm_block_hash_reporting(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            number: BlockNumber,
+            hash: &H256,
+        ) -> Result<Vec<H256>, Error> {
             let number = number as i64;
 
             match self {
@@ -568,19 +1052,24 @@ mod data {
                         .filter(b::network_name.eq(chain))
                         .filter(b::number.eq(number))
                         .filter(b::hash.ne(&hash))
-                        .execute(conn)
-                        .map_err(Error::from)
+                        .returning(b::hash)
+                        .get_results::<String>(conn)?
+                        .into_iter()
+                        .map(|hash| normalize_block_hash(&hash).map_err(Error::from))
+                        .collect()
                 }
                 Storage::Private(Schema { blocks, .. }) => {
                     let query = format!(
-                        "delete from {} where number = $1 and hash != $2",
+                        "delete from {} where number = $1 and hash != $2 returning hash",
                         blocks.qname
                     );
                     sql_query(query)
                         .bind::<BigInt, _>(number)
                         .bind::<Bytea, _>(hash.as_bytes())
-                        .execute(conn)
-                        .map_err(Error::from)
+                        .load::<BlockHashBytea>(conn)?
+                        .into_iter()
+                        .map(|row| h256_from_bytes(&row.hash).map_err(Error::from))
+                        .collect()
                 }
             }
         }
@@ -615,26 +1104,113 @@ mod data {
                 .transpose()
         }
 
-        /// Find the first block that is missing from the database needed to
-        /// complete the chain from block `hash` to the block with number
-        /// `first_block`.
-        pub(super) fn missing_parent(
+        /// Return the parent hash of the block with the given `hash`, or
+        /// `None` if we do not have that block
+        pub(super) fn parent_hash(
             &self,
             conn: &PgConnection,
             chain: &str,
-            first_block: i64,
-            hash: H256,
-            genesis: H256,
+            hash: &H256,
         ) -> Result<Option<H256>, Error> {
             match self {
                 Storage::Shared => {
-                    // We recursively build a temp table 'chain' containing the hash and
-                    // parent_hash of blocks to check. The 'last' value is used to stop
-                    // the recursion and is true if one of these conditions is true:
-                    //   * we are missing a parent block
-                    //   * we checked the required number of blocks
-                    //   * we checked the genesis block
-                    const MISSING_PARENT_SQL: &str = "
+                    use public::ethereum_blocks as b;
+
+                    b::table
+                        .select(b::parent_hash)
+                        .filter(b::network_name.eq(chain))
+                        .filter(b::hash.eq(format!("{:x}", hash)))
+                        .first::<Option<String>>(conn)
+                        .optional()?
+                        .flatten()
+                        .map(|parent| normalize_block_hash(&parent).map_err(Error::from))
+                        .transpose()
+                }
+                Storage::Private(Schema { blocks, .. }) => blocks
+                    .table()
+                    .select(blocks.parent_hash())
+                    .filter(blocks.hash().eq(hash.as_bytes()))
+                    .first::<Vec<u8>>(conn)
+                    .optional()?
+                    .map(|parent| h256_from_bytes(&parent).map_err(Error::from))
+                    .transpose(),
+            }
+        }
+
+        pub(super) fn block_exists(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            hash: &H256,
+        ) -> Result<bool, StoreError> {
+            let number = match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    b::table
+                        .select(b::number)
+                        .filter(b::network_name.eq(chain))
+                        .filter(b::hash.eq(format!("{:x}", hash)))
+                        .first::<i64>(conn)
+                        .optional()?
+                }
+                Storage::Private(Schema { blocks, .. }) => blocks
+                    .table()
+                    .select(blocks.number())
+                    .filter(blocks.hash().eq(hash.as_bytes()))
+                    .first::<i64>(conn)
+                    .optional()?,
+            };
+            Ok(number.is_some())
+        }
+
+        pub(super) fn highest_block_number(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+        ) -> Result<Option<BlockNumber>, StoreError> {
+            let number = match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    b::table
+                        .select(sql::<Nullable<BigInt>>("max(number)"))
+                        .filter(b::network_name.eq(chain))
+                        .first::<Option<i64>>(conn)?
+                }
+                Storage::Private(Schema { blocks, .. }) => blocks
+                    .table()
+                    .select(sql::<Nullable<BigInt>>("max(number)"))
+                    .first::<Option<i64>>(conn)?,
+            };
+            number
+                .map(|number| {
+                    BlockNumber::try_from(number)
+                        .map_err(|e| StoreError::QueryExecutionError(e.to_string()))
+                })
+                .transpose()
+        }
+
+        /// Find the first block that is missing from the database needed to
+        /// complete the chain from block `hash` to the block with number
+        /// `first_block`.
+        pub(super) fn missing_parent(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            first_block: i64,
+            hash: H256,
+            genesis: H256,
+        ) -> Result<Option<H256>, Error> {
+            match self {
+                Storage::Shared => {
+                    // We recursively build a temp table 'chain' containing the hash and
+                    // parent_hash of blocks to check. The 'last' value is used to stop
+                    // the recursion and is true if one of these conditions is true:
+                    //   * we are missing a parent block
+                    //   * we checked the required number of blocks
+                    //   * we checked the genesis block
+                    const MISSING_PARENT_SQL: &str = "
             with recursive chain(hash, parent_hash, last) as (
                 -- base case: look at the head candidate block
                 select b.hash, b.parent_hash, false
@@ -670,7 +1246,7 @@ mod data {
 
                     let missing = match missing.len() {
                         0 => None,
-                        1 => Some(missing[0].hash.parse()?),
+                        1 => Some(normalize_block_hash(&missing[0].hash)?),
                         _ => {
                             unreachable!("the query can only return no or one row");
                         }
@@ -725,6 +1301,93 @@ mod data {
             }
         }
 
+        /// Find all blocks with numbers between `from` and `to` (inclusive)
+        /// whose parent is not the hash of any block we have at the
+        /// preceding block number, i.e., the places where the chain we
+        /// have stored is discontinuous. The `genesis` block is never
+        /// reported since it does not have a parent
+        pub(super) fn find_gaps(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            from: BlockNumber,
+            to: BlockNumber,
+            genesis: &H256,
+        ) -> Result<Vec<(BlockNumber, H256)>, Error> {
+            #[derive(QueryableByName)]
+            struct GapText {
+                #[sql_type = "BigInt"]
+                number: i64,
+                #[sql_type = "Text"]
+                hash: String,
+            }
+
+            #[derive(QueryableByName)]
+            struct GapBytea {
+                #[sql_type = "BigInt"]
+                number: i64,
+                #[sql_type = "Bytea"]
+                hash: Vec<u8>,
+            }
+
+            match self {
+                Storage::Shared => {
+                    const FIND_GAPS_SQL: &str = "
+            select b.number, b.hash
+              from ethereum_blocks b
+             where b.network_name = $1
+               and b.number >= $2
+               and b.number <= $3
+               and b.hash != $4
+               and not exists (select 1
+                                 from ethereum_blocks p
+                                where p.network_name = $1
+                                  and p.number = b.number - 1
+                                  and p.hash = b.parent_hash)
+             order by b.number, b.hash";
+
+                    sql_query(FIND_GAPS_SQL)
+                        .bind::<Text, _>(chain)
+                        .bind::<BigInt, _>(from as i64)
+                        .bind::<BigInt, _>(to as i64)
+                        .bind::<Text, _>(format!("{:x}", genesis))
+                        .load::<GapText>(conn)?
+                        .into_iter()
+                        .map(|gap| {
+                            Ok((gap.number as BlockNumber, normalize_block_hash(&gap.hash)?))
+                        })
+                        .collect()
+                }
+                Storage::Private(Schema { blocks, .. }) => {
+                    // Same as `FIND_GAPS_SQL` except for the table name and
+                    // the missing `network_name` column
+                    let query = format!(
+                        "
+            select b.number, b.hash
+              from {qname} b
+             where b.number >= $1
+               and b.number <= $2
+               and b.hash != $3
+               and not exists (select 1
+                                 from {qname} p
+                                where p.number = b.number - 1
+                                  and p.hash = b.parent_hash)
+             order by b.number, b.hash",
+                        qname = blocks.qname
+                    );
+
+                    sql_query(query)
+                        .bind::<BigInt, _>(from as i64)
+                        .bind::<BigInt, _>(to as i64)
+                        .bind::<Bytea, _>(genesis.as_bytes())
+                        .load::<GapBytea>(conn)?
+                        .into_iter()
+                        .map(|gap| Ok((gap.number as BlockNumber, h256_from_bytes(&gap.hash)?)))
+                        .collect()
+                }
+            }
+        }
+
         /// Return the best candidate for the new chain head if there is a block
         /// with a higher block number than the current chain head. The returned
         /// value if the hash and number of the candidate and the genesis block
@@ -775,20 +1438,7 @@ mod data {
         ) -> Result<Option<json::Value>, Error> {
             let data = match self {
                 Storage::Shared => {
-                    const ANCESTOR_SQL: &str = "
-        with recursive ancestors(block_hash, block_offset) as (
-            values ($1, 0)
-            union all
-            select b.parent_hash, a.block_offset+1
-              from ancestors a, ethereum_blocks b
-             where a.block_hash = b.hash
-               and a.block_offset < $2
-        )
-        select a.block_hash as hash
-          from ancestors a
-         where a.block_offset = $2;";
-
-                    let hash = sql_query(ANCESTOR_SQL)
+                    let hash = sql_query(ancestor_sql("ethereum_blocks"))
                         .bind::<Text, _>(block_ptr.hash_hex())
                         .bind::<BigInt, _>(offset as i64)
                         .get_result::<BlockHashText>(conn)
@@ -807,24 +1457,7 @@ mod data {
                     }
                 }
                 Storage::Private(Schema { blocks, .. }) => {
-                    // Same as ANCESTOR_SQL except for the table name
-                    let query = format!(
-                        "
-        with recursive ancestors(block_hash, block_offset) as (
-            values ($1, 0)
-            union all
-            select b.parent_hash, a.block_offset+1
-              from ancestors a, {} b
-             where a.block_hash = b.hash
-               and a.block_offset < $2
-        )
-        select a.block_hash as hash
-          from ancestors a
-         where a.block_offset = $2;",
-                        blocks.qname
-                    );
-
-                    let hash = sql_query(query)
+                    let hash = sql_query(ancestor_sql(&blocks.qname))
                         .bind::<Bytea, _>(block_ptr.hash_slice())
                         .bind::<BigInt, _>(offset as i64)
                         .get_result::<BlockHashBytea>(conn)
@@ -859,11 +1492,68 @@ mod data {
             Ok(data)
         }
 
+        /// Return the timestamp of the ancestor at `offset` of `block_ptr`
+        /// as it is stored in the block, usually a hex string like
+        /// `0x5f5e100`. Returns `None` if there is no such ancestor and
+        /// `Some(None)` if the ancestor does not have a timestamp. The
+        /// timestamp is extracted in the database so that we do not have
+        /// to load and deserialize the whole block
+        pub(super) fn ancestor_timestamp(
+            &self,
+            conn: &PgConnection,
+            block_ptr: BlockPtr,
+            offset: BlockNumber,
+        ) -> Result<Option<Option<String>>, Error> {
+            // see also 7736e440-4c6b-11ec-8c4d-b42e99f52061
+            const TIMESTAMP: &str =
+                "coalesce(data -> 'block' ->> 'timestamp', data ->> 'timestamp')";
+
+            let timestamp = match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    let hash = sql_query(ancestor_sql("ethereum_blocks"))
+                        .bind::<Text, _>(block_ptr.hash_hex())
+                        .bind::<BigInt, _>(offset as i64)
+                        .get_result::<BlockHashText>(conn)
+                        .optional()?;
+                    match hash {
+                        None => None,
+                        Some(hash) => b::table
+                            .filter(b::hash.eq(hash.hash))
+                            .select(sql::<Nullable<Text>>(TIMESTAMP))
+                            .first::<Option<String>>(conn)
+                            .optional()?,
+                    }
+                }
+                Storage::Private(Schema { blocks, .. }) => {
+                    let hash = sql_query(ancestor_sql(&blocks.qname))
+                        .bind::<Bytea, _>(block_ptr.hash_slice())
+                        .bind::<BigInt, _>(offset as i64)
+                        .get_result::<BlockHashBytea>(conn)
+                        .optional()?;
+                    match hash {
+                        None => None,
+                        Some(hash) => blocks
+                            .table()
+                            .filter(blocks.hash().eq(hash.hash))
+                            .select(sql::<Nullable<Text>>(TIMESTAMP))
+                            .first::<Option<String>>(conn)
+                            .optional()?,
+                    }
+                }
+            };
+            Ok(timestamp)
+        }
+
+        /// Delete all blocks with a number less than `block`. The
+        /// `genesis` block is never deleted, no matter what its number is
         pub(super) fn delete_blocks_before(
             &self,
             conn: &PgConnection,
             chain: &str,
             block: i64,
+            genesis: &H256,
         ) -> Result<usize, Error> {
             match self {
                 Storage::Shared => {
@@ -872,23 +1562,97 @@ mod data {
                     diesel::delete(b::table)
                         .filter(b::network_name.eq(chain))
                         .filter(b::number.lt(block))
-                        .filter(b::number.gt(0))
+                        .filter(b::hash.ne(format!("{:x}", genesis)))
                         .execute(conn)
                         .map_err(Error::from)
                 }
                 Storage::Private(Schema { blocks, .. }) => {
                     let query = format!(
-                        "delete from {} where number < $1 and number > 0",
+                        "delete from {} where number < $1 and hash != $2",
                         blocks.qname
                     );
                     sql_query(query)
                         .bind::<BigInt, _>(block)
+                        .bind::<Bytea, _>(genesis.as_bytes())
+                        .execute(conn)
+                        .map_err(Error::from)
+                }
+            }
+        }
+
+        /// Delete all blocks with a number between `from` and `to`,
+        /// inclusive. The `genesis` block is never deleted
+        pub(super) fn delete_blocks_in_range(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            from: i64,
+            to: i64,
+            genesis: &H256,
+        ) -> Result<usize, Error> {
+            match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    diesel::delete(b::table)
+                        .filter(b::network_name.eq(chain))
+                        .filter(b::number.ge(from))
+                        .filter(b::number.le(to))
+                        .filter(b::hash.ne(format!("{:x}", genesis)))
+                        .execute(conn)
+                        .map_err(Error::from)
+                }
+                Storage::Private(Schema { blocks, .. }) => {
+                    let query = format!(
+                        "delete from {} where number >= $1 and number <= $2 and hash != $3",
+                        blocks.qname
+                    );
+                    sql_query(query)
+                        .bind::<BigInt, _>(from)
+                        .bind::<BigInt, _>(to)
+                        .bind::<Bytea, _>(genesis.as_bytes())
                         .execute(conn)
                         .map_err(Error::from)
                 }
             }
         }
 
+        /// Delete all blocks with a number greater than `number` and
+        /// return their hashes
+        pub(super) fn delete_blocks_above(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            number: i64,
+        ) -> Result<Vec<H256>, Error> {
+            match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    diesel::delete(b::table)
+                        .filter(b::network_name.eq(chain))
+                        .filter(b::number.gt(number))
+                        .returning(b::hash)
+                        .get_results::<String>(conn)?
+                        .into_iter()
+                        .map(|hash| normalize_block_hash(&hash).map_err(Error::from))
+                        .collect()
+                }
+                Storage::Private(Schema { blocks, .. }) => {
+                    let query = format!(
+                        "delete from {} where number > $1 returning hash",
+                        blocks.qname
+                    );
+                    sql_query(query)
+                        .bind::<BigInt, _>(number)
+                        .load::<BlockHashBytea>(conn)?
+                        .into_iter()
+                        .map(|row| h256_from_bytes(&row.hash).map_err(Error::from))
+                        .collect()
+                }
+            }
+        }
+
         pub(super) fn get_call_and_access(
             &self,
             conn: &PgConnection,
@@ -936,22 +1700,107 @@ mod data {
             }
         }
 
-        pub(super) fn update_accessed_at(
+        /// Look up all the cached calls with the given `ids` in one query.
+        /// For each call that is in the cache, return a tuple of its id,
+        /// return value, contract address and whether the `accessed_at`
+        /// date for the contract needs to be updated
+        pub(super) fn get_calls_and_access(
             &self,
             conn: &PgConnection,
-            contract_address: &[u8],
+            ids: &[&[u8]],
+        ) -> Result<Vec<(Vec<u8>, Vec<u8>, Vec<u8>, bool)>, Error> {
+            use diesel::dsl::any;
+
+            match self {
+                Storage::Shared => {
+                    use public::eth_call_cache as cache;
+                    use public::eth_call_meta as meta;
+
+                    cache::table
+                        .inner_join(meta::table)
+                        .filter(cache::id.eq(any(ids)))
+                        .select((
+                            cache::id,
+                            cache::return_value,
+                            cache::contract_address,
+                            sql::<Bool>("CURRENT_DATE > eth_call_meta.accessed_at"),
+                        ))
+                        .load(conn)
+                        .map_err(Error::from)
+                }
+                Storage::Private(Schema {
+                    call_cache,
+                    call_meta,
+                    ..
+                }) => call_cache
+                    .table()
+                    .inner_join(
+                        call_meta.table().on(call_meta
+                            .contract_address()
+                            .eq(call_cache.contract_address())),
+                    )
+                    .filter(call_cache.id().eq(any(ids)))
+                    .select((
+                        call_cache.id(),
+                        call_cache.return_value(),
+                        call_cache.contract_address(),
+                        sql::<Bool>(&format!(
+                            "CURRENT_DATE > {}.{}",
+                            CallMetaTable::TABLE_NAME,
+                            CallMetaTable::ACCESSED_AT
+                        )),
+                    ))
+                    .load(conn)
+                    .map_err(Error::from),
+            }
+        }
+
+        /// Set `accessed_at` to today for all the given contracts
+        pub(super) fn update_accessed_at_many(
+            &self,
+            conn: &PgConnection,
+            contract_addresses: &[&[u8]],
         ) -> Result<(), Error> {
+            use diesel::dsl::any;
+
             let result = match self {
                 Storage::Shared => {
                     use public::eth_call_meta as meta;
 
-                    update(meta::table.find(contract_address.as_ref()))
+                    update(meta::table.filter(meta::contract_address.eq(any(contract_addresses))))
                         .set(meta::accessed_at.eq(sql("CURRENT_DATE")))
                         .execute(conn)
                 }
                 Storage::Private(Schema { call_meta, .. }) => {
                     let query = format!(
-                        "update {} set accessed_at = CURRENT_DATE where contract_address = $1",
+                        "update {} set accessed_at = CURRENT_DATE \
+                          where contract_address = any($1)",
+                        call_meta.qname
+                    );
+                    sql_query(query)
+                        .bind::<Array<Bytea>, _>(contract_addresses)
+                        .execute(conn)
+                }
+            };
+            result.map(|_| ()).map_err(Error::from)
+        }
+
+        pub(super) fn update_accessed_at(
+            &self,
+            conn: &PgConnection,
+            contract_address: &[u8],
+        ) -> Result<(), Error> {
+            let result = match self {
+                Storage::Shared => {
+                    use public::eth_call_meta as meta;
+
+                    update(meta::table.find(contract_address.as_ref()))
+                        .set(meta::accessed_at.eq(sql("CURRENT_DATE")))
+                        .execute(conn)
+                }
+                Storage::Private(Schema { call_meta, .. }) => {
+                    let query = format!(
+                        "update {} set accessed_at = CURRENT_DATE where contract_address = $1",
                         call_meta.qname
                     );
                     sql_query(query)
@@ -1062,6 +1911,216 @@ mod data {
             result.map(|_| ()).map_err(Error::from)
         }
 
+        /// Insert all `calls` into the call cache, where each call is a
+        /// tuple of its id, contract address, block number and return
+        /// value, and mark each of the `contract_addresses` as accessed
+        /// today. Calls that are already cached are left untouched. The
+        /// `contract_addresses` must not contain duplicates
+        pub(super) fn set_calls(
+            &self,
+            conn: &PgConnection,
+            calls: &[(&[u8], &[u8], i32, &[u8])],
+            contract_addresses: &[&[u8]],
+        ) -> Result<(), Error> {
+            use diesel::dsl::any;
+
+            if calls.is_empty() {
+                return Ok(());
+            }
+
+            match self {
+                Storage::Shared => {
+                    use public::eth_call_cache as cache;
+                    use public::eth_call_meta as meta;
+
+                    let rows: Vec<_> = calls
+                        .iter()
+                        .map(|(id, contract_address, block_number, return_value)| {
+                            (
+                                cache::id.eq(*id),
+                                cache::contract_address.eq(*contract_address),
+                                cache::block_number.eq(*block_number),
+                                cache::return_value.eq(*return_value),
+                            )
+                        })
+                        .collect();
+                    insert_into(cache::table)
+                        .values(rows)
+                        .on_conflict_do_nothing()
+                        .execute(conn)?;
+
+                    // Only touch contracts that have not been accessed
+                    // today; see `set_call` for why it is ok that this
+                    // check is racy
+                    let fresh: HashSet<Vec<u8>> = meta::table
+                        .filter(meta::contract_address.eq(any(contract_addresses)))
+                        .filter(sql::<Bool>("accessed_at >= current_date"))
+                        .select(meta::contract_address)
+                        .load::<Vec<u8>>(conn)?
+                        .into_iter()
+                        .collect();
+                    let rows: Vec<_> = contract_addresses
+                        .iter()
+                        .filter(|contract_address| !fresh.contains(**contract_address))
+                        .map(|contract_address| {
+                            (
+                                meta::contract_address.eq(*contract_address),
+                                meta::accessed_at.eq(sql("CURRENT_DATE")),
+                            )
+                        })
+                        .collect();
+                    if !rows.is_empty() {
+                        insert_into(meta::table)
+                            .values(rows)
+                            .on_conflict(meta::contract_address)
+                            .do_update()
+                            .set(meta::accessed_at.eq(sql("CURRENT_DATE")))
+                            .execute(conn)?;
+                    }
+                }
+                Storage::Private(Schema {
+                    call_cache,
+                    call_meta,
+                    ..
+                }) => {
+                    let ids: Vec<_> = calls.iter().map(|call| call.0).collect();
+                    let addresses: Vec<_> = calls.iter().map(|call| call.1).collect();
+                    let numbers: Vec<_> = calls.iter().map(|call| call.2).collect();
+                    let return_values: Vec<_> = calls.iter().map(|call| call.3).collect();
+
+                    let query = format!(
+                        "insert into {}(id, contract_address, block_number, return_value) \
+                         select * from unnest($1::bytea[], $2::bytea[], $3::int4[], $4::bytea[]) \
+                         on conflict do nothing",
+                        call_cache.qname
+                    );
+                    sql_query(query)
+                        .bind::<Array<Bytea>, _>(ids)
+                        .bind::<Array<Bytea>, _>(addresses)
+                        .bind::<Array<Integer>, _>(numbers)
+                        .bind::<Array<Bytea>, _>(return_values)
+                        .execute(conn)?;
+
+                    let query = format!(
+                        "insert into {} as m(contract_address, accessed_at) \
+                         select contract_address, CURRENT_DATE \
+                           from unnest($1::bytea[]) as t(contract_address) \
+                         on conflict(contract_address) \
+                         do update set accessed_at = CURRENT_DATE \
+                                 where m.accessed_at < CURRENT_DATE",
+                        call_meta.qname
+                    );
+                    sql_query(query)
+                        .bind::<Array<Bytea>, _>(contract_addresses)
+                        .execute(conn)?;
+                }
+            }
+            Ok(())
+        }
+
+        /// Delete the cached call with the given `id` and return whether
+        /// there was such a call
+        pub(super) fn remove_call(&self, conn: &PgConnection, id: &[u8]) -> Result<bool, Error> {
+            let count = match self {
+                Storage::Shared => {
+                    use public::eth_call_cache as cache;
+
+                    diesel::delete(cache::table.filter(cache::id.eq(id))).execute(conn)?
+                }
+                Storage::Private(Schema { call_cache, .. }) => {
+                    let query = format!("delete from {} where id = $1", call_cache.qname);
+                    sql_query(query).bind::<Bytea, _>(id).execute(conn)?
+                }
+            };
+            Ok(count > 0)
+        }
+
+        /// Delete all cached calls for contracts whose calls have not been
+        /// accessed in the last `days` days and return how many calls were
+        /// deleted
+        pub(super) fn remove_stale_calls(
+            &self,
+            conn: &PgConnection,
+            days: i64,
+        ) -> Result<usize, Error> {
+            let query = match self {
+                Storage::Shared => "delete from eth_call_cache c \
+                                     using eth_call_meta m \
+                                    where c.contract_address = m.contract_address \
+                                      and m.accessed_at < CURRENT_DATE - $1::int"
+                    .to_string(),
+                Storage::Private(Schema {
+                    call_cache,
+                    call_meta,
+                    ..
+                }) => format!(
+                    "delete from {} c \
+                      using {} m \
+                     where c.contract_address = m.contract_address \
+                       and m.accessed_at < CURRENT_DATE - $1::int",
+                    call_cache.qname, call_meta.qname
+                ),
+            };
+            sql_query(query)
+                .bind::<BigInt, _>(days)
+                .execute(conn)
+                .map_err(Error::from)
+        }
+
+        /// Delete all cached calls against blocks with a number less than
+        /// `block`, except for calls against the `genesis` block, and
+        /// return how many calls were deleted. The call cache for
+        /// `Storage::Shared` is used by all chains and we can not tell which
+        /// chain a call belongs to; we therefore never delete anything
+        /// from it
+        pub(super) fn delete_calls_before(
+            &self,
+            conn: &PgConnection,
+            block: BlockNumber,
+            genesis: BlockNumber,
+        ) -> Result<usize, Error> {
+            match self {
+                Storage::Shared => Ok(0),
+                Storage::Private(Schema { call_cache, .. }) => {
+                    let query = format!(
+                        "delete from {} where block_number < $1 and block_number != $2",
+                        call_cache.qname
+                    );
+                    sql_query(query)
+                        .bind::<Integer, _>(block)
+                        .bind::<Integer, _>(genesis)
+                        .execute(conn)
+                        .map_err(Error::from)
+                }
+            }
+        }
+
+        /// Pretend that calls for `contract_address` were last accessed
+        /// `days` days ago
+        #[cfg(debug_assertions)]
+        pub(super) fn backdate_call_access(
+            &self,
+            conn: &PgConnection,
+            contract_address: &[u8],
+            days: i64,
+        ) -> Result<(), Error> {
+            let table = match self {
+                Storage::Shared => "eth_call_meta",
+                Storage::Private(Schema { call_meta, .. }) => &call_meta.qname,
+            };
+            let query = format!(
+                "update {} set accessed_at = CURRENT_DATE - $2::int \
+                  where contract_address = $1",
+                table
+            );
+            sql_query(query)
+                .bind::<Bytea, _>(contract_address)
+                .bind::<BigInt, _>(days)
+                .execute(conn)
+                .map(|_| ())
+                .map_err(Error::from)
+        }
+
         #[cfg(debug_assertions)]
         // used by `super::set_chain` for test support
         pub(super) fn set_chain(
@@ -1111,6 +2170,8 @@ mod data {
                     n::genesis_block_hash.eq(genesis_hash),
                     n::head_block_hash.eq::<Option<&str>>(None),
                     n::head_block_number.eq::<Option<i64>>(None),
+                    n::ingest_cursor_hash.eq::<Option<&str>>(None),
+                    n::ingest_cursor_number.eq::<Option<i64>>(None),
                 ))
                 .execute(conn)
                 .unwrap();
@@ -1168,6 +2229,32 @@ from (
     }
 }
 
+/// How many rows `ChainStore::prune_to_depth` deleted from each table
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    pub blocks: usize,
+    pub calls: usize,
+}
+
+/// What `attempt_chain_head_update` would do if it ran now, as reported by
+/// `ChainStore::chain_head_update_preview`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeadUpdatePreview {
+    /// The block with the highest number above the current chain head,
+    /// or `None` if there is no such block
+    pub candidate: Option<BlockPtr>,
+    /// A block on the chain leading up to `candidate` that we do not have
+    /// yet. The chain head is only moved to `candidate` if this is `None`
+    pub missing_parent: Option<H256>,
+}
+
+/// Receives timing information for the database operations of a
+/// `ChainStore`, for example to export them to Prometheus
+pub trait ChainStoreMetrics: Send + Sync + 'static {
+    /// The operation `op` took `duration` and read or wrote `rows` rows
+    fn record_op(&self, op: &str, duration: Duration, rows: usize);
+}
+
 pub struct ChainStore {
     pool: ConnectionPool,
     pub chain: String,
@@ -1175,6 +2262,20 @@ pub struct ChainStore {
     genesis_block_ptr: BlockPtr,
     status: ChainStatus,
     chain_head_update_sender: ChainHeadUpdateSender,
+    /// The pools for all shards. We need them to find the slowest
+    /// deployment on this chain, no matter which shard it is stored in
+    shards: HashMap<Shard, ConnectionPool>,
+    /// How long to wait for a database connection before giving up with
+    /// `StoreError::ConnectionTimeout`
+    conn_timeout: Duration,
+    /// Where to report how long database operations take. Operations are
+    /// not timed when this is `None`
+    metrics: RwLock<Option<Arc<dyn ChainStoreMetrics>>>,
+    /// Every asynchronous write holds a read lock on this until it is
+    /// done; `flush` takes the write lock to wait for all of them
+    in_flight: Arc<tokio::sync::RwLock<()>>,
+    /// The `statement_timeout` for queries that walk the chain
+    statement_timeout: RwLock<Option<Duration>>,
 }
 
 impl ChainStore {
@@ -1185,6 +2286,9 @@ impl ChainStore {
         status: ChainStatus,
         chain_head_update_sender: ChainHeadUpdateSender,
         pool: ConnectionPool,
+        shards: HashMap<Shard, ConnectionPool>,
+        conn_timeout: Duration,
+        metrics: Option<Arc<dyn ChainStoreMetrics>>,
     ) -> Self {
         let store = ChainStore {
             pool,
@@ -1193,20 +2297,43 @@ impl ChainStore {
             genesis_block_ptr: BlockPtr::new(net_identifier.genesis_block_hash.clone(), 0),
             status,
             chain_head_update_sender,
+            shards,
+            conn_timeout,
+            metrics: RwLock::new(metrics),
+            in_flight: Arc::new(tokio::sync::RwLock::new(())),
+            statement_timeout: RwLock::new(None),
         };
 
         store
     }
 
+    /// Report the timings of database operations to `metrics` from now
+    /// on, or stop reporting them if `metrics` is `None`
+    pub fn set_metrics(&self, metrics: Option<Arc<dyn ChainStoreMetrics>>) {
+        *self.metrics.write().unwrap() = metrics;
+    }
+
+    /// Report that the operation `op`, which started at `start`, just
+    /// finished and touched `rows` rows
+    fn record_op(&self, op: &str, start: Instant, rows: usize) {
+        if let Some(metrics) = self.metrics.read().unwrap().as_ref() {
+            metrics.record_op(op, start.elapsed(), rows);
+        }
+    }
+
     pub fn is_ingestible(&self) -> bool {
         matches!(self.status, ChainStatus::Ingestible)
     }
 
-    fn get_conn(&self) -> Result<PooledConnection<ConnectionManager<PgConnection>>, Error> {
-        self.pool.get().map_err(Error::from)
+    fn get_conn(&self) -> Result<PooledConnection<ConnectionManager<PgConnection>>, StoreError> {
+        self.pool.get_timeout(self.conn_timeout)
     }
 
-    pub(crate) fn create(&self, ident: &ChainIdentifier) -> Result<(), Error> {
+    /// Record this chain in `ethereum_networks` and create its storage. If
+    /// the chain is already recorded with a different `net_version` or
+    /// genesis block than `ident`, fail since we would otherwise mix data
+    /// from different networks
+    pub fn create(&self, ident: &ChainIdentifier) -> Result<(), Error> {
         use public::ethereum_networks::dsl::*;
 
         let conn = self.get_conn()?;
@@ -1223,6 +2350,7 @@ impl ChainStore {
                 .on_conflict(name)
                 .do_nothing()
                 .execute(&conn)?;
+            self.check_network(&conn, ident)?;
             self.storage.create(&conn)
         })?;
 
@@ -1247,9 +2375,34 @@ impl ChainStore {
     ) -> Result<HashMap<String, BlockPtr>, StoreError> {
         use public::ethereum_networks as n;
 
-        let pointers: Vec<(String, BlockPtr)> = n::table
+        let heads = n::table
             .select((n::name, n::head_block_hash, n::head_block_number))
-            .load::<(String, Option<String>, Option<i64>)>(conn)?
+            .load::<(String, Option<String>, Option<i64>)>(conn)?;
+        Self::head_pointers_from_rows(heads)
+    }
+
+    /// Like `chain_head_pointers`, but only look at the chains whose name
+    /// is in `names`
+    pub fn chain_head_pointers_for(
+        conn: &PgConnection,
+        names: &[&str],
+    ) -> Result<HashMap<String, BlockPtr>, StoreError> {
+        use diesel::dsl::any;
+        use public::ethereum_networks as n;
+
+        let heads = n::table
+            .filter(n::name.eq(any(names)))
+            .select((n::name, n::head_block_hash, n::head_block_number))
+            .load::<(String, Option<String>, Option<i64>)>(conn)?;
+        Self::head_pointers_from_rows(heads)
+    }
+
+    /// Turn rows of `(name, head_block_hash, head_block_number)` into a map
+    /// from chain name to head pointer, skipping chains without a head
+    fn head_pointers_from_rows(
+        heads: Vec<(String, Option<String>, Option<i64>)>,
+    ) -> Result<HashMap<String, BlockPtr>, StoreError> {
+        let pointers: Vec<(String, BlockPtr)> = heads
             .into_iter()
             .filter_map(|(name, hash, number)| match (hash, number) {
                 (Some(hash), Some(number)) => Some((name, hash, number)),
@@ -1284,17 +2437,883 @@ impl ChainStore {
         )
     }
 
+    /// The `net_version` that was recorded for this chain when it was
+    /// created
+    pub fn net_version(&self) -> Result<String, Error> {
+        use public::ethereum_networks as n;
+
+        n::table
+            .filter(n::name.eq(&self.chain))
+            .select(n::net_version)
+            .first::<String>(&self.get_conn()?)
+            .map_err(Error::from)
+    }
+
+    /// The hash of the genesis block that was recorded for this chain when
+    /// it was created
+    pub fn stored_genesis_hash(&self) -> Result<H256, Error> {
+        use public::ethereum_networks as n;
+
+        let hash = n::table
+            .filter(n::name.eq(&self.chain))
+            .select(n::genesis_block_hash)
+            .first::<String>(&self.get_conn()?)?;
+        normalize_block_hash(&hash).map_err(|e| {
+            anyhow!(
+                "genesis block hash `{}` for chain {} is invalid: {}",
+                hash,
+                self.chain,
+                e
+            )
+        })
+    }
+
+    /// Check that the `net_version` and genesis block hash we have stored
+    /// for this chain match `ident`, and return an error describing the
+    /// mismatch if they do not
+    pub fn verify_network(&self, ident: &ChainIdentifier) -> Result<(), Error> {
+        let conn = self.get_conn()?;
+        self.check_network(&conn, ident)
+    }
+
+    fn check_network(&self, conn: &PgConnection, ident: &ChainIdentifier) -> Result<(), Error> {
+        use public::ethereum_networks as n;
+
+        let (net_version, genesis) = n::table
+            .filter(n::name.eq(&self.chain))
+            .select((n::net_version, n::genesis_block_hash))
+            .first::<(String, String)>(conn)?;
+        if net_version != ident.net_version {
+            return Err(anyhow!(
+                "chain {} has net_version `{}` but the provider reports `{}`",
+                self.chain,
+                net_version,
+                ident.net_version
+            ));
+        }
+
+        let genesis = normalize_block_hash(&genesis)
+            .map(BlockHash::from)
+            .map_err(|e| {
+                anyhow!(
+                    "genesis block hash `{}` for chain {} is invalid: {}",
+                    genesis,
+                    self.chain,
+                    e
+                )
+            })?;
+        if genesis != ident.genesis_block_hash {
+            return Err(anyhow!(
+                "chain {} has genesis block {} but the provider reports {}",
+                self.chain,
+                genesis,
+                ident.genesis_block_hash
+            ));
+        }
+        Ok(())
+    }
+
     /// Store the given chain as the blocks for the `network` set the
     /// network's genesis block to `genesis_hash`, and head block to
     /// `null`
     #[cfg(debug_assertions)]
     pub fn set_chain(&self, genesis_hash: &str, chain: Vec<&dyn Block>) {
-        let conn = self.pool.get().expect("can get a database connection");
+        let conn = self.get_conn().expect("can get a database connection");
 
         self.storage
             .set_chain(&conn, &self.chain, genesis_hash, chain);
     }
 
+    /// Write `hash` and `number` into the head block pointer for this
+    /// chain without any validation. Only useful to set up tests that need
+    /// to see how we deal with bad data in `ethereum_networks`
+    #[cfg(debug_assertions)]
+    pub fn set_chain_head_raw(&self, hash: Option<&str>, number: Option<i64>) {
+        use public::ethereum_networks as n;
+
+        let conn = self.get_conn().expect("can get a database connection");
+        update(n::table.filter(n::name.eq(&self.chain)))
+            .set((n::head_block_hash.eq(hash), n::head_block_number.eq(number)))
+            .execute(&conn)
+            .expect("can update the chain head");
+    }
+
+    /// Remember that all blocks up to and including `block` have been
+    /// fully processed. Unlike the chain head, which can be ahead of what
+    /// has actually been processed, the cursor records durable progress
+    /// and is where ingestion should resume after a restart
+    pub fn set_ingest_cursor(&self, block: BlockPtr) -> Result<(), Error> {
+        use public::ethereum_networks as n;
+
+        let conn = self.get_conn()?;
+        update(n::table.filter(n::name.eq(&self.chain)))
+            .set((
+                n::ingest_cursor_hash.eq(block.hash_hex()),
+                n::ingest_cursor_number.eq(block.number as i64),
+            ))
+            .execute(&conn)?;
+        Ok(())
+    }
+
+    /// Return the block set with the most recent call to
+    /// `set_ingest_cursor`, or `None` if ingestion has not recorded any
+    /// progress yet
+    pub fn ingest_cursor(&self) -> Result<Option<BlockPtr>, Error> {
+        use public::ethereum_networks as n;
+
+        let cursor = n::table
+            .select((n::ingest_cursor_hash, n::ingest_cursor_number))
+            .filter(n::name.eq(&self.chain))
+            .first::<(Option<String>, Option<i64>)>(&*self.get_conn()?)
+            .optional()?;
+
+        match cursor {
+            None | Some((None, None)) => Ok(None),
+            Some((Some(hash), Some(number))) => {
+                let hash = normalize_block_hash(&hash).map_err(|e| {
+                    constraint_violation!(
+                        "ingest cursor hash `{}` for chain {} is not a valid hash: {}",
+                        hash,
+                        self.chain,
+                        e
+                    )
+                })?;
+                Ok(Some((hash, number).into()))
+            }
+            Some((hash, number)) => Err(constraint_violation!(
+                "ingest cursor for chain {} is only partially set: hash is {:?} and number is {:?}",
+                self.chain,
+                hash,
+                number
+            )
+            .into()),
+        }
+    }
+
+    /// Return `true` if the block with the given hash is in the store
+    pub fn block_exists(&self, hash: &H256) -> Result<bool, Error> {
+        let conn = self.get_conn()?;
+        self.storage
+            .block_exists(&conn, &self.chain, hash)
+            .map_err(Error::from)
+    }
+
+    /// Return the highest block number of any block in the store, or
+    /// `None` if the store does not contain any blocks
+    pub fn highest_block_number(&self) -> Result<Option<BlockNumber>, Error> {
+        let conn = self.get_conn()?;
+        self.storage
+            .highest_block_number(&conn, &self.chain)
+            .map_err(Error::from)
+    }
+
+    /// Look up the blocks with the given `numbers` with a single query and
+    /// return pointers to them grouped by block number. Numbers for which
+    /// we have no block are not in the map; numbers with several competing
+    /// blocks map to all of them
+    pub fn block_ptrs_by_numbers(
+        &self,
+        numbers: &[BlockNumber],
+    ) -> Result<HashMap<BlockNumber, Vec<BlockPtr>>, Error> {
+        let conn = self.get_conn()?;
+        let ptrs = self
+            .storage
+            .block_ptrs_by_numbers(&conn, &self.chain, numbers)?;
+
+        let mut map: HashMap<BlockNumber, Vec<BlockPtr>> = HashMap::new();
+        for ptr in ptrs {
+            map.entry(ptr.number).or_default().push(ptr);
+        }
+        Ok(map)
+    }
+
+    /// Look up the blocks with the given `hashes` and return them together
+    /// with their transaction receipts. Hashes for which we have no block
+    /// are ignored
+    pub fn full_blocks(&self, hashes: Vec<H256>) -> Result<Vec<EthereumBlock>, Error> {
+        let start = Instant::now();
+        let conn = self.get_conn()?;
+        let blocks = self
+            .storage
+            .full_blocks(&conn, &self.chain, &hashes)?
+            .into_iter()
+            .map(|block| {
+                json::from_value::<EthereumBlock>(block).map_err(|source| {
+                    StoreError::Deserialization {
+                        context: format!("block data for chain `{}`", self.chain),
+                        source,
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.record_op("full_blocks", start, blocks.len());
+        Ok(blocks)
+    }
+
+    /// Return the `n` blocks with the highest numbers that we have, ordered
+    /// by number descending and, for blocks with the same number, by hash.
+    /// The chain head is not consulted, so that this also works while the
+    /// chain is being reorganized
+    pub fn latest_blocks(&self, n: usize) -> Result<Vec<LightEthereumBlock>, Error> {
+        let start = Instant::now();
+        let conn = self.get_conn()?;
+        let blocks = self
+            .storage
+            .latest_blocks(&conn, &self.chain, n)?
+            .into_iter()
+            .map(|block| {
+                json::from_value::<LightEthereumBlock>(block).map_err(|source| {
+                    StoreError::Deserialization {
+                        context: format!("block data for chain `{}`", self.chain),
+                        source,
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.record_op("latest_blocks", start, blocks.len());
+        Ok(blocks)
+    }
+
+    /// Return pointers to all blocks whose parent is the block with
+    /// `parent_hash`. Together with the parent hash of each block, this
+    /// makes it possible to walk the block tree in both directions
+    pub fn children_of(&self, parent_hash: &H256) -> Result<Vec<BlockPtr>, Error> {
+        let conn = self.get_conn()?;
+        self.storage.children_of(&conn, &self.chain, parent_hash)
+    }
+
+    /// Return the stored data for the genesis block of this chain, or
+    /// `None` if the genesis block has not been ingested yet
+    pub fn genesis_block(&self) -> Result<Option<EthereumBlock>, Error> {
+        let hash = self.genesis_block_ptr.hash_as_h256();
+        Ok(self.full_blocks(vec![hash])?.into_iter().next())
+    }
+
+    /// Delete all blocks with numbers from `from` to `to`, inclusive,
+    /// while keeping the genesis block. Returns the number of deleted
+    /// blocks
+    pub fn delete_blocks_in_range(
+        &self,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Result<usize, Error> {
+        let conn = self.get_conn()?;
+        self.storage.delete_blocks_in_range(
+            &conn,
+            &self.chain,
+            from as i64,
+            to as i64,
+            &self.genesis_block_ptr.hash_as_h256(),
+        )
+    }
+
+    /// Remove all blocks from the cache that are below `min_keep` or more
+    /// than `ancestor_count` blocks behind the chain head, whichever is
+    /// lower, but retain the genesis block. `cleanup_cached_blocks` calls
+    /// this with the head of the slowest deployment across all shards as
+    /// `min_keep`
+    pub fn cleanup_cached_blocks_with_floor(
+        &self,
+        min_keep: BlockNumber,
+        ancestor_count: BlockNumber,
+    ) -> Result<Option<(BlockNumber, usize)>, Error> {
+        let floor = match self.chain_head_block(&self.chain)? {
+            Some(head) => min_keep.min(head - ancestor_count),
+            None => min_keep,
+        };
+        let conn = self.get_conn()?;
+        self.delete_blocks_below(&conn, floor)
+    }
+
+    /// Delete all blocks that are more than `keep_blocks` blocks behind the
+    /// chain head together with the calls cached for them, but retain the
+    /// genesis block. Everything is deleted in a single transaction. If
+    /// the chain has no head yet, nothing is deleted
+    pub fn prune_to_depth(&self, keep_blocks: BlockNumber) -> Result<PruneReport, Error> {
+        use public::ethereum_networks as n;
+
+        if keep_blocks < 0 {
+            return Err(anyhow!(
+                "the number of blocks to keep must not be negative, but is {}",
+                keep_blocks
+            ));
+        }
+
+        let conn = self.get_conn()?;
+        conn.transaction(|| {
+            let head = n::table
+                .filter(n::name.eq(&self.chain))
+                .select(n::head_block_number)
+                .first::<Option<i64>>(&*conn)?;
+            let floor = match head {
+                Some(head) => head - keep_blocks as i64,
+                None => return Ok(PruneReport::default()),
+            };
+            let genesis = &self.genesis_block_ptr;
+            if floor <= genesis.number as i64 {
+                return Ok(PruneReport::default());
+            }
+
+            let blocks = self.storage.delete_blocks_before(
+                &conn,
+                &self.chain,
+                floor,
+                &genesis.hash_as_h256(),
+            )?;
+            let calls =
+                self.storage
+                    .delete_calls_before(&conn, floor as BlockNumber, genesis.number)?;
+            Ok(PruneReport { blocks, calls })
+        })
+    }
+
+    /// Delete all blocks with a number less than `block` except for the
+    /// genesis block, and return `block` and the number of deleted blocks.
+    /// If `block` is not above the genesis block, do nothing
+    /// Return the smallest head block of all deployments for this chain
+    /// that are assigned to a node and have not failed, across all
+    /// shards. Returns `None` if there are no such deployments
+    fn min_subgraph_head(&self) -> Result<Option<BlockNumber>, Error> {
+        use diesel::sql_types::{Integer, Nullable};
+
+        #[derive(QueryableByName)]
+        struct MinBlock {
+            #[sql_type = "Nullable<Integer>"]
+            block: Option<i32>,
+        }
+
+        let mut min_head: Option<BlockNumber> = None;
+        for (shard, pool) in &self.shards {
+            // Deployment metadata is stored in the deployment's shard, but
+            // assignments only exist in the primary
+            let assignments = if shard == &*PRIMARY_SHARD {
+                "subgraphs".to_string()
+            } else {
+                ForeignServer::metadata_schema(&*PRIMARY_SHARD)
+            };
+            let query = format!(
+                "
+            select min(d.latest_ethereum_block_number)::int as block
+              from subgraphs.subgraph_deployment d,
+                   {}.subgraph_deployment_assignment a,
+                   {}.deployment_schemas ds
+             where ds.id = d.id
+               and a.id = d.id
+               and not d.failed
+               and ds.shard = $1
+               and ds.network = $2",
+                assignments,
+                ForeignServer::PRIMARY_PUBLIC
+            );
+            let conn = pool.get_timeout(self.conn_timeout)?;
+            let head = diesel::sql_query(query)
+                .bind::<Text, _>(shard.as_str())
+                .bind::<Text, _>(&self.chain)
+                .get_result::<MinBlock>(&conn)?
+                .block;
+            min_head = match (min_head, head) {
+                (Some(min_head), Some(head)) => Some(min_head.min(head)),
+                (min_head, head) => min_head.or(head),
+            };
+        }
+        Ok(min_head)
+    }
+
+    fn delete_blocks_below(
+        &self,
+        conn: &PgConnection,
+        block: BlockNumber,
+    ) -> Result<Option<(BlockNumber, usize)>, Error> {
+        if block > self.genesis_block_ptr.number {
+            self.storage
+                .delete_blocks_before(
+                    conn,
+                    &self.chain,
+                    block as i64,
+                    &self.genesis_block_ptr.hash_as_h256(),
+                )
+                .map(|rows| Some((block, rows)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Remove all cached `eth_call` results for contracts whose calls
+    /// have not been accessed in the last `older_than_days` days. Returns
+    /// the number of cached calls that were removed
+    pub fn remove_stale_calls(&self, older_than_days: i64) -> Result<usize, Error> {
+        let conn = self.get_conn()?;
+        conn.transaction(|| self.storage.remove_stale_calls(&conn, older_than_days))
+    }
+
+    /// Like `EthereumCallCache::set_call`, but if the call is already
+    /// cached with a different return value, return an error instead of
+    /// silently keeping the cached value. Such a mismatch points to a
+    /// collision of call ids or to a bug that produced a wrong return value
+    /// at some point.
+    ///
+    /// A call that some other process caches between our check and our
+    /// insert is not compared, but that is very unlikely
+    pub fn set_call_strict(
+        &self,
+        contract_address: ethabi::Address,
+        encoded_call: &[u8],
+        block: BlockPtr,
+        return_value: &[u8],
+    ) -> Result<(), Error> {
+        let id = contract_call_id(&contract_address, encoded_call, &block);
+        let conn = &*self.get_conn()?;
+        conn.transaction(|| {
+            let cached = self.storage.get_call_and_access(conn, id.as_ref())?;
+            match cached {
+                Some((cached, _)) if cached != return_value => Err(anyhow!(
+                    "call to contract 0x{} at block {} is cached with return value 0x{} \
+                     but the new return value is 0x{}",
+                    hex::encode(contract_address.as_ref()),
+                    block,
+                    hex::encode(&cached),
+                    hex::encode(return_value)
+                )),
+                Some(_) => Ok(()),
+                None => self.storage.set_call(
+                    conn,
+                    id.as_ref(),
+                    contract_address.as_ref(),
+                    block.number as i32,
+                    return_value,
+                ),
+            }
+        })
+    }
+
+    /// Make it look like the calls for `contract_address` were last
+    /// accessed `days` days ago
+    #[cfg(debug_assertions)]
+    pub fn backdate_call_access(
+        &self,
+        contract_address: ethabi::Address,
+        days: i64,
+    ) -> Result<(), Error> {
+        let conn = self.get_conn()?;
+        self.storage
+            .backdate_call_access(&conn, contract_address.as_ref(), days)
+    }
+
+    /// Insert all `blocks`, overwriting any that are already in the
+    /// store. The blocks are written in one transaction, so that either
+    /// all of them or none of them end up in the store
+    pub async fn upsert_blocks(&self, blocks: Vec<Arc<dyn Block>>) -> Result<(), Error> {
+        let start = Instant::now();
+        let count = blocks.len();
+        let pool = self.pool.clone();
+        let network = self.chain.clone();
+        let storage = self.storage.clone();
+        let in_flight = self.start_write().await;
+        pool.with_conn_timeout(self.conn_timeout, move |conn, _| {
+            let _in_flight = in_flight;
+            let blocks: Vec<&dyn Block> = blocks.iter().map(|block| block.as_ref()).collect();
+            conn.transaction(|| {
+                storage
+                    .upsert_blocks(&conn, &network, &blocks, true)
+                    .map_err(CancelableError::from)
+            })
+        })
+        .await
+        .map_err(Error::from)?;
+        self.record_op("upsert_blocks", start, count);
+        Ok(())
+    }
+
+    /// Delete all blocks with the given `number` other than the one with
+    /// `hash` and return the hashes of the blocks that were deleted
+    pub fn confirm_block_hash_reporting(
+        &self,
+        number: BlockNumber,
+        hash: &H256,
+    ) -> Result<Vec<H256>, Error> {
+        let conn = self.get_conn()?;
+        self.storage
+            .confirm_block_hash_reporting(&conn, &self.chain, number, hash)
+    }
+
+    /// Make `new_head` the chain head after a reorg. In one transaction,
+    /// delete all blocks above `new_head`, delete all other blocks at its
+    /// height, and point the chain head at it. Returns the hashes of the
+    /// deleted blocks. Fails without changing anything if we do not have
+    /// `new_head`
+    pub async fn reorg_to(&self, new_head: BlockPtr) -> Result<Vec<H256>, Error> {
+        use public::ethereum_networks as n;
+
+        let start = Instant::now();
+        let pool = self.pool.clone();
+        let chain = self.chain.clone();
+        let storage = self.storage.clone();
+        let head = new_head.clone();
+        let in_flight = self.start_write().await;
+        let (removed, previous) = pool
+            .with_conn_timeout(self.conn_timeout, move |conn, _| {
+                let _in_flight = in_flight;
+                let hash = head.hash_as_h256();
+                let number = head.number as i64;
+                conn.transaction(|| -> Result<_, StoreError> {
+                    let known = storage
+                        .block_hashes_by_block_number(conn, &chain, head.number)?
+                        .contains(&hash);
+                    if !known {
+                        return Err(anyhow!(
+                            "can not reorg chain `{}` to block #{} ({}) since we do not have that block",
+                            chain,
+                            head.number,
+                            head.hash_hex()
+                        )
+                        .into());
+                    }
+
+                    let previous = n::table
+                        .filter(n::name.eq(&chain))
+                        .select((n::head_block_hash, n::head_block_number))
+                        .for_update()
+                        .first::<(Option<String>, Option<i64>)>(conn)?;
+                    let previous = match previous {
+                        (Some(hash), Some(number)) => Some((hash, number)),
+                        _ => None,
+                    };
+
+                    let mut removed = storage.delete_blocks_above(conn, &chain, number)?;
+                    removed.extend(storage.confirm_block_hash_reporting(
+                        conn,
+                        &chain,
+                        head.number,
+                        &hash,
+                    )?);
+                    update(n::table.filter(n::name.eq(&chain)))
+                        .set((
+                            n::head_block_hash.eq(head.hash_hex()),
+                            n::head_block_number.eq(number),
+                        ))
+                        .execute(conn)?;
+                    Ok((removed, previous))
+                })
+                .map_err(CancelableError::from)
+            })
+            .await?;
+
+        let previous = previous
+            .as_ref()
+            .map(|(hash, number)| (hash.as_str(), *number));
+        self.chain_head_update_sender.send(
+            &new_head.hash_hex(),
+            new_head.number as i64,
+            previous,
+        )?;
+        self.record_op("reorg_to", start, removed.len());
+        Ok(removed)
+    }
+
+    /// Iterate over all blocks with numbers in `range` in order of their
+    /// block number. The blocks are loaded from the database `batch`
+    /// blocks at a time and only deserialized when the iterator gets to
+    /// them
+    pub fn blocks_in_range(
+        &self,
+        range: Range<BlockNumber>,
+        batch: usize,
+    ) -> Result<impl Iterator<Item = Result<LightEthereumBlock, Error>> + '_, Error> {
+        ensure!(
+            batch > 0,
+            "the batch size for loading blocks must be positive"
+        );
+        Ok(BlocksInRange {
+            store: self,
+            range,
+            batch,
+            after: None,
+            buffer: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    /// Wait until all writes that were started before this call have
+    /// finished, and then send an update for the current chain head.
+    /// Writes that are started while we wait are held up until we are
+    /// done. During a graceful shutdown, callers must call this before
+    /// dropping the store so that no writes are lost
+    pub async fn flush(&self) -> Result<(), Error> {
+        drop(self.in_flight.write().await);
+        self.notify_current_head()
+    }
+
+    /// Report what `attempt_chain_head_update` would do right now without
+    /// changing the chain head or sending a chain head update
+    pub async fn chain_head_update_preview(
+        self: Arc<Self>,
+        ancestor_count: BlockNumber,
+    ) -> Result<HeadUpdatePreview, Error> {
+        let chain_store = self.clone();
+        let preview = self
+            .pool
+            .with_conn_timeout(self.conn_timeout, move |conn, _| {
+                Ok(chain_store
+                    .head_update_preview(conn, ancestor_count)
+                    .map_err(CancelableError::from)?)
+            })
+            .await?;
+        Ok(preview)
+    }
+
+    /// Find the candidate for the next chain head and check whether we
+    /// have all the blocks up to `ancestor_count` blocks before it
+    fn head_update_preview(
+        &self,
+        conn: &PgConnection,
+        ancestor_count: BlockNumber,
+    ) -> Result<HeadUpdatePreview, Error> {
+        use public::ethereum_networks as n;
+
+        // Looking for missing parents walks the chain with a recursive query
+        self.with_statement_timeout(conn, || {
+            let candidate = self.storage.chain_head_candidate(conn, &self.chain)?;
+            let (ptr, first_block) = match &candidate {
+                None => {
+                    return Ok(HeadUpdatePreview {
+                        candidate,
+                        missing_parent: None,
+                    })
+                }
+                Some(ptr) => (ptr, 0.max(ptr.number.saturating_sub(ancestor_count))),
+            };
+
+            // In the common case, the candidate is a child of the current
+            // chain head. We already checked that the chain leading up to the
+            // current head is complete when we made it the head, and can skip
+            // the more expensive check for missing parents
+            let head_hash = n::table
+                .filter(n::name.eq(&self.chain))
+                .select(n::head_block_hash)
+                .first::<Option<String>>(conn)?;
+            let extends_head = match head_hash {
+                Some(head_hash) => {
+                    let parent =
+                        self.storage
+                            .parent_hash(conn, &self.chain, &ptr.hash_as_h256())?;
+                    parent.map(|parent| format!("{:x}", parent)) == Some(head_hash)
+                }
+                None => false,
+            };
+
+            let missing_parent = if extends_head {
+                None
+            } else {
+                self.storage.missing_parent(
+                    conn,
+                    &self.chain,
+                    first_block as i64,
+                    ptr.hash_as_h256(),
+                    self.genesis_block_ptr.hash_as_h256(),
+                )?
+            };
+
+            Ok(HeadUpdatePreview {
+                candidate,
+                missing_parent,
+            })
+        })
+    }
+
+    /// Use `timeout` as the Postgres `statement_timeout` for queries that
+    /// walk the chain, like looking for missing parents during a chain head
+    /// update or finding an ancestor block. Those queries fail with
+    /// `StoreError::StatementTimeout` when they take longer. With `None`,
+    /// which is the default, they are never cut short
+    pub fn set_statement_timeout(&self, timeout: Option<Duration>) {
+        *self.statement_timeout.write().unwrap() = timeout;
+    }
+
+    /// Run `f` in a transaction that uses our statement timeout, or
+    /// without a transaction if we do not have a timeout
+    fn with_statement_timeout<T>(
+        &self,
+        conn: &PgConnection,
+        f: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        fn is_statement_timeout(e: &Error) -> bool {
+            use diesel::result::Error::DatabaseError;
+
+            let e = match e.downcast_ref::<StoreError>() {
+                Some(StoreError::Unknown(e)) => e,
+                _ => e,
+            };
+            match e.downcast_ref::<diesel::result::Error>() {
+                Some(DatabaseError(_, info)) => info.message().contains("statement timeout"),
+                _ => false,
+            }
+        }
+
+        let timeout = match *self.statement_timeout.read().unwrap() {
+            Some(timeout) => timeout,
+            None => return f(),
+        };
+        conn.transaction(|| {
+            conn.batch_execute(&format!(
+                "set local statement_timeout = {}",
+                timeout.as_millis()
+            ))?;
+            f()
+        })
+        .map_err(|e| {
+            if is_statement_timeout(&e) {
+                StoreError::StatementTimeout.into()
+            } else {
+                e
+            }
+        })
+    }
+
+    /// Mark the start of an asynchronous write. The write counts as in
+    /// flight until the returned guard is dropped; the guard should be
+    /// moved into the closure that does the actual work so that it is
+    /// released only once that work is finished
+    async fn start_write(&self) -> tokio::sync::OwnedRwLockReadGuard<()> {
+        self.in_flight.clone().read_owned().await
+    }
+
+    /// Send a chain head update for the current chain head to anybody
+    /// listening for them. This lets subscribers that started after the
+    /// last head update catch up, e.g., at startup, and makes sure that
+    /// they have seen the latest head before shutdown. Does nothing if the
+    /// chain does not have a head yet. It is safe to call this repeatedly
+    pub fn notify_current_head(&self) -> Result<(), Error> {
+        if let Some(head) = self.chain_head_ptr()? {
+            self.chain_head_update_sender
+                .send(&head.hash_hex(), head.number as i64, None)?;
+        }
+        Ok(())
+    }
+
+    /// Return the block at height `number` on the canonical chain, i.e.,
+    /// the chain that ends in the current chain head. Unlike
+    /// `block_hashes_by_block_number`, this ignores blocks that were
+    /// reorged out. Returns `None` if there is no chain head, if the chain
+    /// head is below `number`, or if we are missing a block between the
+    /// chain head and `number`
+    pub fn canonical_block_at(
+        &self,
+        number: BlockNumber,
+    ) -> Result<Option<LightEthereumBlock>, Error> {
+        let block = match self.chain_head_ptr()? {
+            Some(head) if head.number >= number => {
+                let offset = head.number - number;
+                self.ancestor_block(head, offset)?
+            }
+            _ => None,
+        };
+        // See `Storage::blocks` for why we look at `data -> 'block'`
+        block
+            .map(|mut data| {
+                let block = match data.get_mut("block") {
+                    Some(block) => block.take(),
+                    None => data,
+                };
+                json::from_value::<LightEthereumBlock>(block).map_err(|source| {
+                    StoreError::Deserialization {
+                        context: format!("block data for chain `{}`", self.chain),
+                        source,
+                    }
+                    .into()
+                })
+            })
+            .transpose()
+    }
+
+    /// Return the timestamp of the block at height `number` on the
+    /// canonical chain as seconds since the Unix epoch. Returns `None` in
+    /// the same situations in which `canonical_block_at` does, and an error
+    /// if the block has no timestamp or one that does not fit into a `u64`
+    pub fn block_time(&self, number: BlockNumber) -> Result<Option<u64>, Error> {
+        let head = match self.chain_head_ptr()? {
+            Some(head) if head.number >= number => head,
+            _ => return Ok(None),
+        };
+        let offset = head.number - number;
+
+        let start = Instant::now();
+        let conn = self.get_conn()?;
+        let timestamp = self.with_statement_timeout(&conn, || {
+            self.storage.ancestor_timestamp(&conn, head, offset)
+        })?;
+        self.record_op("block_time", start, timestamp.is_some() as usize);
+
+        let timestamp = match timestamp {
+            None => return Ok(None),
+            Some(Some(timestamp)) => timestamp,
+            Some(None) => return Err(anyhow!("block {} does not have a timestamp", number)),
+        };
+        // Timestamps are stored as hex strings like `0x5f5e100`
+        u64::from_str_radix(timestamp.trim_start_matches("0x"), 16)
+            .map(Some)
+            .map_err(|e| {
+                anyhow!(
+                    "invalid timestamp `{}` for block {}: {}",
+                    timestamp,
+                    number,
+                    e
+                )
+            })
+    }
+
+    /// Return the number and hash of all blocks with numbers between
+    /// `from` and `to` (inclusive) whose parent is not stored at the
+    /// preceding block number. An empty result means that the blocks we
+    /// have in that range form unbroken chains
+    pub fn find_gaps(
+        &self,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Result<Vec<(BlockNumber, H256)>, Error> {
+        let conn = self.get_conn()?;
+        self.storage.find_gaps(
+            &conn,
+            &self.chain,
+            from,
+            to,
+            &self.genesis_block_ptr.hash_as_h256(),
+        )
+    }
+
+    /// Check that the database tables for this chain exist. See
+    /// `Storage::check_storage`
+    pub fn check_storage(&self) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        self.storage.check_storage(&conn)
+    }
+
+    /// Rename `table` in the private schema of this chain; does nothing
+    /// for chains stored in the shared schema. Only useful in tests
+    #[cfg(debug_assertions)]
+    pub fn rename_table(&self, table: &str, new_name: &str) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        self.storage.rename_table(&conn, table, new_name)
+    }
+
+    /// Return a store for the same chain that gets its connections from
+    /// `pool` and waits at most `conn_timeout` for one. Only useful in tests
+    #[cfg(debug_assertions)]
+    pub fn with_pool(&self, pool: ConnectionPool, conn_timeout: Duration) -> ChainStore {
+        ChainStore {
+            pool,
+            chain: self.chain.clone(),
+            storage: self.storage.clone(),
+            genesis_block_ptr: self.genesis_block_ptr.clone(),
+            status: self.status,
+            chain_head_update_sender: self.chain_head_update_sender.clone(),
+            shards: self.shards.clone(),
+            conn_timeout,
+            metrics: RwLock::new(self.metrics.read().unwrap().clone()),
+            in_flight: Arc::new(tokio::sync::RwLock::new(())),
+            statement_timeout: RwLock::new(*self.statement_timeout.read().unwrap()),
+        }
+    }
+
     pub fn truncate_block_cache(&self) -> Result<(), StoreError> {
         let conn = self.get_conn()?;
         self.storage.truncate_block_cache(&conn)?;
@@ -1302,6 +3321,59 @@ impl ChainStore {
     }
 }
 
+/// Iterator returned by `ChainStore::blocks_in_range`. It keeps track of
+/// the last block it loaded and uses that to load the next batch of blocks
+/// once it has handed out all the blocks it has in memory
+struct BlocksInRange<'a> {
+    store: &'a ChainStore,
+    range: Range<BlockNumber>,
+    batch: usize,
+    after: Option<(BlockNumber, H256)>,
+    buffer: VecDeque<json::Value>,
+    done: bool,
+}
+
+impl BlocksInRange<'_> {
+    fn load_batch(&mut self) -> Result<(), Error> {
+        let conn = self.store.get_conn()?;
+        let blocks = self.store.storage.blocks_in_range(
+            &conn,
+            &self.store.chain,
+            &self.range,
+            self.after.as_ref(),
+            self.batch,
+        )?;
+        self.done = blocks.len() < self.batch;
+        for (number, hash, data) in blocks {
+            self.after = Some((number, hash));
+            self.buffer.push_back(data);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for BlocksInRange<'_> {
+    type Item = Result<LightEthereumBlock, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buffer.is_empty() && !self.done {
+            if let Err(e) = self.load_batch() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        self.buffer.pop_front().map(|block| {
+            json::from_value::<LightEthereumBlock>(block).map_err(|source| {
+                StoreError::Deserialization {
+                    context: format!("block data for chain `{}`", self.store.chain),
+                    source,
+                }
+                .into()
+            })
+        })
+    }
+}
+
 #[async_trait]
 impl ChainStoreTrait for ChainStore {
     fn genesis_block_ptr(&self) -> Result<BlockPtr, Error> {
@@ -1309,10 +3381,13 @@ impl ChainStoreTrait for ChainStore {
     }
 
     async fn upsert_block(&self, block: Arc<dyn Block>) -> Result<(), Error> {
+        let start = Instant::now();
         let pool = self.pool.clone();
         let network = self.chain.clone();
         let storage = self.storage.clone();
-        pool.with_conn(move |conn, _| {
+        let in_flight = self.start_write().await;
+        pool.with_conn_timeout(self.conn_timeout, move |conn, _| {
+            let _in_flight = in_flight;
             conn.transaction(|| {
                 storage
                     .upsert_block(&conn, &network, block.as_ref(), true)
@@ -1320,15 +3395,19 @@ impl ChainStoreTrait for ChainStore {
             })
         })
         .await
-        .map_err(Error::from)
+        .map_err(Error::from)?;
+        self.record_op("upsert_block", start, 1);
+        Ok(())
     }
 
     fn upsert_light_blocks(&self, blocks: &[&dyn Block]) -> Result<(), Error> {
-        let conn = self.pool.get()?;
+        let start = Instant::now();
+        let conn = self.get_conn()?;
         for block in blocks {
             self.storage
                 .upsert_block(&conn, &self.chain, *block, false)?;
         }
+        self.record_op("upsert_light_blocks", start, blocks.len());
         Ok(())
     }
 
@@ -1340,54 +3419,52 @@ impl ChainStoreTrait for ChainStore {
 
         let (missing, ptr) = {
             let chain_store = self.clone();
+            let in_flight = self.start_write().await;
             self.pool
-                .with_conn(move |conn, _| {
-                    let candidate = chain_store
-                        .storage
-                        .chain_head_candidate(&conn, &chain_store.chain)
+                .with_conn_timeout(self.conn_timeout, move |conn, _| {
+                    let _in_flight = in_flight;
+                    let preview = chain_store
+                        .head_update_preview(&conn, ancestor_count)
                         .map_err(CancelableError::from)?;
-                    let (ptr, first_block) = match &candidate {
-                        None => return Ok((None, None)),
-                        Some(ptr) => (ptr, 0.max(ptr.number.saturating_sub(ancestor_count))),
+                    let ptr = match (preview.candidate, preview.missing_parent) {
+                        (_, Some(missing)) => return Ok((Some(missing), None)),
+                        (None, None) => return Ok((None, None)),
+                        (Some(ptr), None) => ptr,
                     };
 
-                    match chain_store
-                        .storage
-                        .missing_parent(
-                            &conn,
-                            &chain_store.chain,
-                            first_block as i64,
-                            ptr.hash_as_h256(),
-                            chain_store.genesis_block_ptr.hash_as_h256(),
-                        )
-                        .map_err(CancelableError::from)?
-                    {
-                        Some(missing) => {
-                            return Ok((Some(missing), None));
-                        }
-                        None => { /* we have a complete chain, no missing parents */ }
-                    }
-
                     let hash = ptr.hash_hex();
                     let number = ptr.number as i64;
 
-                    conn.transaction(
-                        || -> Result<(Option<H256>, Option<(String, i64)>), StoreError> {
-                            update(n::table.filter(n::name.eq(&chain_store.chain)))
-                                .set((
-                                    n::head_block_hash.eq(&hash),
-                                    n::head_block_number.eq(number),
-                                ))
-                                .execute(conn)?;
-                            Ok((None, Some((hash, number))))
-                        },
-                    )
+                    conn.transaction(|| -> Result<_, StoreError> {
+                        // Remember the old head so that listeners can tell
+                        // whether the chain was reorganized
+                        let previous = n::table
+                            .filter(n::name.eq(&chain_store.chain))
+                            .select((n::head_block_hash, n::head_block_number))
+                            .for_update()
+                            .first::<(Option<String>, Option<i64>)>(conn)?;
+                        let previous = match previous {
+                            (Some(hash), Some(number)) => Some((hash, number)),
+                            _ => None,
+                        };
+                        update(n::table.filter(n::name.eq(&chain_store.chain)))
+                            .set((
+                                n::head_block_hash.eq(&hash),
+                                n::head_block_number.eq(number),
+                            ))
+                            .execute(conn)?;
+                        Ok((None, Some((hash, number, previous))))
+                    })
                     .map_err(CancelableError::from)
                 })
                 .await?
         };
-        if let Some((hash, number)) = ptr {
-            self.chain_head_update_sender.send(&hash, number)?;
+        if let Some((hash, number, previous)) = ptr {
+            let previous = previous
+                .as_ref()
+                .map(|(hash, number)| (hash.as_str(), *number));
+            self.chain_head_update_sender
+                .send(&hash, number, previous)?;
         }
 
         Ok(missing)
@@ -1396,25 +3473,41 @@ impl ChainStoreTrait for ChainStore {
     fn chain_head_ptr(&self) -> Result<Option<BlockPtr>, Error> {
         use public::ethereum_networks::dsl::*;
 
-        ethereum_networks
+        let head = ethereum_networks
             .select((head_block_hash, head_block_number))
             .filter(name.eq(&self.chain))
-            .load::<(Option<String>, Option<i64>)>(&*self.get_conn()?)
-            .map(|rows| {
-                rows.first()
-                    .map(|(hash_opt, number_opt)| match (hash_opt, number_opt) {
-                        (Some(hash), Some(number)) => Some((hash.parse().unwrap(), *number).into()),
-                        (None, None) => None,
-                        _ => unreachable!(),
-                    })
-                    .and_then(|opt| opt)
-            })
-            .map_err(Error::from)
+            .first::<(Option<String>, Option<i64>)>(&*self.get_conn()?)
+            .optional()?;
+
+        match head {
+            None | Some((None, None)) => Ok(None),
+            Some((Some(hash), Some(number))) => {
+                let hash = normalize_block_hash(&hash).map_err(|e| {
+                    constraint_violation!(
+                        "head block hash `{}` for chain {} is not a valid hash: {}",
+                        hash,
+                        self.chain,
+                        e
+                    )
+                })?;
+                Ok(Some((hash, number).into()))
+            }
+            Some((hash, number)) => Err(constraint_violation!(
+                "head block for chain {} is only partially set: hash is {:?} and number is {:?}",
+                self.chain,
+                hash,
+                number
+            )
+            .into()),
+        }
     }
 
     fn blocks(&self, hashes: &[H256]) -> Result<Vec<json::Value>, Error> {
+        let start = Instant::now();
         let conn = self.get_conn()?;
-        self.storage.blocks(&conn, &self.chain, hashes)
+        let blocks = self.storage.blocks(&conn, &self.chain, hashes)?;
+        self.record_op("blocks", start, blocks.len());
+        Ok(blocks)
     }
 
     fn ancestor_block(
@@ -1429,22 +3522,19 @@ impl ChainStoreTrait for ChainStore {
             block_ptr.hash_hex()
         );
 
+        let start = Instant::now();
         let conn = self.get_conn()?;
-        self.storage.ancestor_block(&conn, block_ptr, offset)
+        let block = self.with_statement_timeout(&conn, || {
+            self.storage.ancestor_block(&conn, block_ptr, offset)
+        })?;
+        self.record_op("ancestor_block", start, block.is_some() as usize);
+        Ok(block)
     }
 
     fn cleanup_cached_blocks(
         &self,
         ancestor_count: BlockNumber,
     ) -> Result<Option<(BlockNumber, usize)>, Error> {
-        use diesel::sql_types::Integer;
-
-        #[derive(QueryableByName)]
-        struct MinBlock {
-            #[sql_type = "Integer"]
-            block: i32,
-        }
-
         // Remove all blocks from the cache that are behind the slowest
         // subgraph's head block, but retain the genesis block. We stay
         // behind the slowest subgraph so that we do not interfere with its
@@ -1452,65 +3542,39 @@ impl ChainStoreTrait for ChainStore {
         // We also stay `ancestor_count` many blocks behind the head of the
         // chain since the block ingestor consults these blocks frequently
         //
-        // Only consider active subgraphs that have not failed
-
-        // This assumes that subgraph metadata and blocks are stored in the
-        // same shard. We disallow setting GRAPH_ETHEREUM_CLEANUP_BLOCKS in
-        // graph_node::config so that we only run this query when we know
-        // it will work. Running this with a sharded store might remove
-        // blocks that are still needed by deployments in other shard
-        //
-        // See 8b6ad0c64e244023ac20ced7897fe666
-
-        let conn = self.get_conn()?;
-        let query = "
-            select coalesce(
-                   least(a.block,
-                        (select head_block_number::int - $1
-                           from ethereum_networks
-                          where name = $2)), -1)::int as block
-              from (
-                select min(d.latest_ethereum_block_number) as block
-                  from subgraphs.subgraph_deployment d,
-                       subgraphs.subgraph_deployment_assignment a,
-                       deployment_schemas ds
-                 where ds.subgraph = d.deployment
-                   and a.id = d.id
-                   and not d.failed
-                   and ds.network = $2) a;";
-        let ancestor_count = i32::try_from(ancestor_count)
-            .expect("ancestor_count fits into a signed 32 bit integer");
-        diesel::sql_query(query)
-            .bind::<Integer, _>(ancestor_count)
-            .bind::<Text, _>(&self.chain)
-            .load::<MinBlock>(&conn)?
-            .first()
-            .map(|MinBlock { block }| {
-                // If we could not determine a minimum block, the query
-                // returns -1, and we should not do anything. We also guard
-                // against removing the genesis block
-                if *block > 0 {
-                    self.storage
-                        .delete_blocks_before(&conn, &self.chain, *block as i64)
-                        .map(|rows| Some((*block, rows)))
-                } else {
-                    Ok(None)
+        // Only consider active subgraphs that have not failed. They can be
+        // in any shard, not just the one that stores the blocks
+        match self.min_subgraph_head()? {
+            Some(min_keep) => self.cleanup_cached_blocks_with_floor(min_keep, ancestor_count),
+            // Without any subgraphs, we only stay behind the chain head
+            None => match self.chain_head_block(&self.chain)? {
+                Some(head) => {
+                    let conn = self.get_conn()?;
+                    self.delete_blocks_below(&conn, head - ancestor_count)
                 }
-            })
-            .unwrap_or(Ok(None))
-            .map_err(|e| e.into())
+                None => Ok(None),
+            },
+        }
     }
 
     fn block_hashes_by_block_number(&self, number: BlockNumber) -> Result<Vec<H256>, Error> {
+        let start = Instant::now();
         let conn = self.get_conn()?;
-        self.storage
-            .block_hashes_by_block_number(&conn, &self.chain, number)
+        let hashes = self
+            .storage
+            .block_hashes_by_block_number(&conn, &self.chain, number)?;
+        self.record_op("block_hashes_by_block_number", start, hashes.len());
+        Ok(hashes)
     }
 
     fn confirm_block_hash(&self, number: BlockNumber, hash: &H256) -> Result<usize, Error> {
+        let start = Instant::now();
         let conn = self.get_conn()?;
-        self.storage
-            .confirm_block_hash(&conn, &self.chain, number, hash)
+        let removed = self
+            .storage
+            .confirm_block_hash(&conn, &self.chain, number, hash)?;
+        self.record_op("confirm_block_hash", start, removed);
+        Ok(removed)
     }
 
     fn block_number(&self, hash: H256) -> Result<Option<(String, BlockNumber)>, StoreError> {
@@ -1528,7 +3592,7 @@ impl ChainStoreTrait for ChainStore {
         let pool = self.pool.clone();
         let storage = self.storage.clone();
         let block_hash = block_hash.to_owned();
-        pool.with_conn(move |conn, _| {
+        pool.with_conn_timeout(self.conn_timeout, move |conn, _| {
             storage
                 .find_transaction_receipts_in_block(&conn, block_hash)
                 .map_err(|e| StoreError::from(e).into())
@@ -1565,6 +3629,46 @@ impl EthereumCallCache for ChainStore {
         }
     }
 
+    fn get_calls(
+        &self,
+        requests: &[(ethabi::Address, Vec<u8>, BlockPtr)],
+    ) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let ids: Vec<[u8; 32]> = requests
+            .iter()
+            .map(|(contract_address, encoded_call, block)| {
+                contract_call_id(contract_address, encoded_call, block)
+            })
+            .collect();
+        let id_refs: Vec<&[u8]> = ids.iter().map(|id| id.as_ref()).collect();
+
+        let conn = &*self.get_conn()?;
+        conn.transaction::<_, Error, _>(|| {
+            let calls = self.storage.get_calls_and_access(conn, &id_refs)?;
+
+            // Update `accessed_at` only once per contract, no matter how
+            // many of its calls we found
+            let stale_contracts: HashSet<&[u8]> = calls
+                .iter()
+                .filter(|(_, _, _, update_accessed_at)| *update_accessed_at)
+                .map(|(_, _, contract_address, _)| contract_address.as_slice())
+                .collect();
+            if !stale_contracts.is_empty() {
+                let stale_contracts: Vec<&[u8]> = stale_contracts.into_iter().collect();
+                self.storage
+                    .update_accessed_at_many(conn, &stale_contracts)?;
+            }
+
+            let return_values: HashMap<&[u8], &Vec<u8>> = calls
+                .iter()
+                .map(|(id, return_value, _, _)| (id.as_slice(), return_value))
+                .collect();
+            Ok(id_refs
+                .iter()
+                .map(|id| return_values.get(id).map(|value| (*value).clone()))
+                .collect())
+        })
+    }
+
     fn set_call(
         &self,
         contract_address: ethabi::Address,
@@ -1584,6 +3688,52 @@ impl EthereumCallCache for ChainStore {
             )
         })
     }
+
+    fn set_calls(
+        &self,
+        calls: &[(ethabi::Address, Vec<u8>, BlockPtr, Vec<u8>)],
+    ) -> Result<(), Error> {
+        let ids: Vec<[u8; 32]> = calls
+            .iter()
+            .map(|(contract_address, encoded_call, block, _)| {
+                contract_call_id(contract_address, encoded_call, block)
+            })
+            .collect();
+        let rows: Vec<(&[u8], &[u8], i32, &[u8])> = calls
+            .iter()
+            .zip(ids.iter())
+            .map(|((contract_address, _, block, return_value), id)| {
+                (
+                    id.as_ref(),
+                    contract_address.as_ref(),
+                    block.number as i32,
+                    return_value.as_slice(),
+                )
+            })
+            .collect();
+
+        // Update `accessed_at` only once per contract, no matter how many
+        // of its calls we are adding
+        let contract_addresses: HashSet<&[u8]> = calls
+            .iter()
+            .map(|(contract_address, _, _, _)| contract_address.as_ref())
+            .collect();
+        let contract_addresses: Vec<&[u8]> = contract_addresses.into_iter().collect();
+
+        let conn = &*self.get_conn()?;
+        conn.transaction(|| self.storage.set_calls(conn, &rows, &contract_addresses))
+    }
+
+    fn remove_call(
+        &self,
+        contract_address: ethabi::Address,
+        encoded_call: &[u8],
+        block: BlockPtr,
+    ) -> Result<bool, Error> {
+        let id = contract_call_id(&contract_address, encoded_call, &block);
+        let conn = self.get_conn()?;
+        self.storage.remove_call(&conn, id.as_ref())
+    }
 }
 
 /// The id is the hashed encoded_call + contract_address + block hash to uniquely identify the call.
@@ -1600,3 +3750,65 @@ fn contract_call_id(
     hash.update(block.hash_slice());
     *hash.finalize().as_bytes()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_storage_namespace() {
+        for namespace in &["chain1", "chain42", "chain007"] {
+            let storage = Storage::private(namespace).expect("valid namespace");
+            assert!(matches!(storage, Storage::Private(_)));
+            assert_eq!(*namespace, storage.to_string());
+        }
+
+        for namespace in &[
+            "", "chain", "public", "sgd1", "chain1a", "chain-1", "Chain1", "chain١",
+        ] {
+            let err = Storage::private(namespace).unwrap_err();
+            assert!(matches!(err, StoreError::InvalidIdentifier(_)));
+            assert!(err.to_string().contains(&format!("`{}`", namespace)));
+        }
+    }
+
+    #[test]
+    fn normalize_block_hash_forms() {
+        const HASH: &str = "8511fa04b64657581e3f00e14543c1d522d5d7e771b54aa3060b662ade47da13";
+        let expected = H256::from_slice(&hex::decode(HASH).unwrap());
+
+        let upper = HASH.to_uppercase();
+        for input in &[
+            HASH.to_string(),
+            format!("0x{}", HASH),
+            format!("0X{}", HASH),
+            upper.clone(),
+            format!("0x{}", upper),
+            "8511FA04b64657581E3F00e14543c1d522d5d7e771b54aa3060b662ade47DA13".to_string(),
+        ] {
+            assert_eq!(expected, normalize_block_hash(input).unwrap(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn normalize_block_hash_rejects_bad_input() {
+        const HASH: &str = "8511fa04b64657581e3f00e14543c1d522d5d7e771b54aa3060b662ade47da13";
+
+        for input in &[
+            "".to_string(),
+            "0x".to_string(),
+            HASH[2..].to_string(),
+            format!("{}00", HASH),
+            format!("0x{}", &HASH[1..]),
+            format!("0x0x{}", &HASH[2..]),
+            format!("{}zz", &HASH[2..]),
+        ] {
+            let err = normalize_block_hash(input).unwrap_err();
+            assert!(
+                matches!(err, StoreError::ConstraintViolation(_)),
+                "{}",
+                input
+            );
+        }
+    }
+}
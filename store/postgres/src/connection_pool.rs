@@ -464,10 +464,35 @@ impl ConnectionPool {
         pool.with_conn(f).await
     }
 
+    /// Like `with_conn`, but wait at most `timeout` for a connection to
+    /// become available instead of the pool's default connection timeout
+    pub(crate) async fn with_conn_timeout<T: Send + 'static>(
+        &self,
+        timeout: Duration,
+        f: impl 'static
+            + Send
+            + FnOnce(
+                &PooledConnection<ConnectionManager<PgConnection>>,
+                &CancelHandle,
+            ) -> Result<T, CancelableError<StoreError>>,
+    ) -> Result<T, StoreError> {
+        let pool = self.get_ready()?;
+        pool.with_conn_timeout(Some(timeout), f).await
+    }
+
     pub fn get(&self) -> Result<PooledConnection<ConnectionManager<PgConnection>>, StoreError> {
         self.get_ready()?.get()
     }
 
+    /// Like `get`, but wait at most `timeout` for a connection to become
+    /// available instead of the pool's default connection timeout
+    pub fn get_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<PooledConnection<ConnectionManager<PgConnection>>, StoreError> {
+        self.get_ready()?.get_timeout(timeout)
+    }
+
     /// Get a connection from the pool for foreign data wrapper access;
     /// since that pool can be very contended, periodically log that we are
     /// still waiting for a connection
@@ -864,6 +889,22 @@ impl PoolInner {
                 &PooledConnection<ConnectionManager<PgConnection>>,
                 &CancelHandle,
             ) -> Result<T, CancelableError<StoreError>>,
+    ) -> Result<T, StoreError> {
+        self.with_conn_timeout(None, f).await
+    }
+
+    /// Like `with_conn`, but if `timeout` is given, wait at most that long
+    /// for a connection and fail with `StoreError::ConnectionTimeout`
+    /// otherwise
+    pub(crate) async fn with_conn_timeout<T: Send + 'static>(
+        &self,
+        timeout: Option<Duration>,
+        f: impl 'static
+            + Send
+            + FnOnce(
+                &PooledConnection<ConnectionManager<PgConnection>>,
+                &CancelHandle,
+            ) -> Result<T, CancelableError<StoreError>>,
     ) -> Result<T, StoreError> {
         let _permit = self.limiter.acquire().await;
         let pool = self.clone();
@@ -878,9 +919,11 @@ impl PoolInner {
 
             // A failure to establish a connection is propagated as though the
             // closure failed.
-            let conn = pool
-                .get()
-                .map_err(|_| CancelableError::Error(StoreError::DatabaseUnavailable))?;
+            let conn = match timeout {
+                Some(timeout) => pool.get_timeout(timeout),
+                None => pool.get(),
+            }
+            .map_err(CancelableError::Error)?;
 
             // It is possible time has passed while establishing a connection.
             // Time to check for cancel.
@@ -908,6 +951,15 @@ impl PoolInner {
         self.pool.get().map_err(|_| StoreError::DatabaseUnavailable)
     }
 
+    pub fn get_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<PooledConnection<ConnectionManager<PgConnection>>, StoreError> {
+        self.pool
+            .get_timeout(timeout)
+            .map_err(|_| StoreError::ConnectionTimeout)
+    }
+
     pub fn get_with_timeout_warning(
         &self,
         logger: &Logger,
@@ -62,7 +62,9 @@ pub mod layout_for_tests {
 
 pub use self::block_store::BlockStore;
 pub use self::chain_head_listener::ChainHeadUpdateListener;
-pub use self::chain_store::ChainStore;
+pub use self::chain_store::{
+    ChainStore, ChainStoreMetrics, HeadUpdatePreview, PruneReport, Storage,
+};
 pub use self::detail::DeploymentDetail;
 pub use self::jobs::register as register_jobs;
 pub use self::notification_listener::NotificationSender;
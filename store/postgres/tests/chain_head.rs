@@ -2,22 +2,27 @@
 //! the chain head pointer gets updated in various situations
 
 use std::future::Future;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use graph::prelude::web3::types::H256;
+use graph::blockchain::{Block, ChainIdentifier};
+use graph::prelude::web3::types::{H256, U256};
 use graph::prelude::{anyhow::anyhow, anyhow::Error};
-use graph::prelude::{serde_json as json, EthereumBlock};
-use graph::prelude::{BlockNumber, QueryStoreManager};
+use graph::prelude::{serde_json as json, EthereumBlock, LightEthereumBlock};
+use graph::prelude::{BlockNumber, BlockPtr, QueryStoreManager, StoreError};
 use graph::{cheap_clone::CheapClone, prelude::web3::types::H160};
 use graph::{components::store::BlockStore as _, prelude::DeploymentHash};
 use graph::{components::store::ChainStore as _, prelude::EthereumCallCache as _};
 use graph_store_postgres::Store as DieselStore;
-use graph_store_postgres::{layout_for_tests::FAKE_NETWORK_SHARED, ChainStore as DieselChainStore};
+use graph_store_postgres::{
+    layout_for_tests::FAKE_NETWORK_SHARED, ChainStore as DieselChainStore, ChainStoreMetrics,
+    PruneReport,
+};
 
 use test_store::block_store::{
     FakeBlock, FakeBlockList, BLOCK_FIVE, BLOCK_FOUR, BLOCK_ONE, BLOCK_ONE_NO_PARENT,
-    BLOCK_ONE_SIBLING, BLOCK_THREE, BLOCK_THREE_NO_PARENT, BLOCK_TWO, BLOCK_TWO_NO_PARENT,
-    GENESIS_BLOCK, NO_PARENT,
+    BLOCK_ONE_SIBLING, BLOCK_SIX_NO_PARENT, BLOCK_THREE, BLOCK_THREE_NO_PARENT, BLOCK_TWO,
+    BLOCK_TWO_NO_PARENT, GENESIS_BLOCK, GENESIS_SIBLING, NO_PARENT,
 };
 use test_store::*;
 
@@ -64,13 +69,26 @@ where
 /// chain. After writing the blocks in `chain` to the store, call
 /// `attempt_chain_head_update` and check its result. Check that the new head
 /// is the one indicated in `head_exp`. If `missing` is not `None`, check that
-/// `attempt_chain_head_update` reports that block as missing
+/// `attempt_chain_head_update` reports that block as missing. Also check
+/// that `chain_head_update_preview` predicts that outcome without changing
+/// the chain head
 fn check_chain_head_update(
     chain: FakeBlockList,
     head_exp: Option<&'static FakeBlock>,
     missing: Option<&'static str>,
 ) {
     run_test_async(chain, move |store, _| async move {
+        let head_before = store.chain_head_ptr().expect("chain_head_ptr failed");
+        let preview = store
+            .clone()
+            .chain_head_update_preview(ANCESTOR_COUNT)
+            .await
+            .expect("chain_head_update_preview failed");
+        assert_eq!(
+            head_before,
+            store.chain_head_ptr().expect("chain_head_ptr failed")
+        );
+
         let missing_act: Vec<_> = store
             .clone()
             .attempt_chain_head_update(ANCESTOR_COUNT)
@@ -88,6 +106,17 @@ fn check_chain_head_update(
             .expect("chain_head_ptr failed")
             .map(|ebp| ebp.hash_hex());
         assert_eq!(head_hash_exp, head_hash_act);
+
+        let missing_preview: Vec<_> = preview
+            .missing_parent
+            .iter()
+            .map(|h| format!("{:x}", h))
+            .collect();
+        assert_eq!(missing_act, missing_preview);
+        if preview.missing_parent.is_none() {
+            let candidate = preview.candidate.map(|ptr| ptr.hash_hex());
+            assert_eq!(head_hash_act, candidate);
+        }
     })
 }
 
@@ -164,6 +193,48 @@ fn long_chain_with_uncles() {
     check_chain_head_update(chain, Some(&*BLOCK_FOUR), None);
 }
 
+#[test]
+fn chain_head_update_extends_head() {
+    // BLOCK_THREE does not have a parent in the store, but since it is
+    // already the chain head, a child of it becomes the new head without
+    // checking the ancestors of BLOCK_THREE again
+    let chain = vec![&*BLOCK_THREE, &*BLOCK_FOUR];
+    run_test_async(chain, |store, _| async move {
+        store.set_chain_head_raw(Some(BLOCK_THREE.hash.as_str()), Some(3));
+        let missing = store
+            .clone()
+            .attempt_chain_head_update(ANCESTOR_COUNT)
+            .await
+            .expect("attempt_chain_head_update failed");
+        assert_eq!(None, missing);
+        assert_eq!(
+            Some(BLOCK_FOUR.block_ptr()),
+            store.chain_head_ptr().expect("chain_head_ptr failed")
+        );
+    })
+}
+
+#[test]
+fn chain_head_update_skips_ahead_of_head() {
+    // BLOCK_FIVE is not a child of the chain head, and we need to check
+    // its ancestors, which finds that the parent of BLOCK_THREE is missing
+    let chain = vec![&*BLOCK_THREE, &*BLOCK_FOUR, &*BLOCK_FIVE];
+    run_test_async(chain, |store, _| async move {
+        store.set_chain_head_raw(Some(BLOCK_THREE.hash.as_str()), Some(3));
+        let missing = store
+            .clone()
+            .attempt_chain_head_update(ANCESTOR_COUNT)
+            .await
+            .expect("attempt_chain_head_update failed")
+            .map(|hash| format!("{:x}", hash));
+        assert_eq!(Some(BLOCK_THREE.parent_hash.clone()), missing);
+        assert_eq!(
+            Some(BLOCK_THREE.block_ptr()),
+            store.chain_head_ptr().expect("chain_head_ptr failed")
+        );
+    })
+}
+
 #[test]
 fn block_number() {
     let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO];
@@ -317,6 +388,50 @@ fn ancestor_block_ommers() {
     });
 }
 
+#[test]
+fn canonical_block_at() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_ONE_SIBLING,
+        &*BLOCK_TWO,
+    ];
+    run_test(chain, move |store, _| {
+        fn canonical_hash(
+            store: &DieselChainStore,
+            number: BlockNumber,
+        ) -> Result<Option<String>, Error> {
+            Ok(store
+                .canonical_block_at(number)?
+                .map(|block| format!("{:x}", block.hash.unwrap())))
+        }
+
+        // Without a chain head, there is no canonical chain
+        assert_eq!(None, canonical_hash(&store, 1)?);
+
+        store.set_chain_head_raw(Some(BLOCK_TWO.hash.as_str()), Some(2));
+        assert_eq!(Some(GENESIS_BLOCK.hash.clone()), canonical_hash(&store, 0)?);
+        assert_eq!(Some(BLOCK_ONE.hash.clone()), canonical_hash(&store, 1)?);
+        assert_eq!(Some(BLOCK_TWO.hash.clone()), canonical_hash(&store, 2)?);
+        assert_eq!(None, canonical_hash(&store, 3)?);
+        Ok(())
+    })
+}
+
+#[test]
+fn notify_current_head() {
+    run_test(vec![&*GENESIS_BLOCK, &*BLOCK_ONE], move |store, _| {
+        // Without a chain head, there is nothing to send
+        store.notify_current_head()?;
+
+        store.set_chain_head_raw(Some(BLOCK_ONE.hash.as_str()), Some(1));
+        store.notify_current_head()?;
+        store.notify_current_head()?;
+        assert_eq!(Some(BLOCK_ONE.block_ptr()), store.chain_head_ptr()?);
+        Ok(())
+    })
+}
+
 #[test]
 fn eth_call_cache() {
     let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO];
@@ -372,3 +487,1161 @@ fn test_transaction_receipts_in_block_function() {
         assert!(receipts.is_empty())
     })
 }
+
+#[test]
+fn chain_head_ptr_with_bad_data() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE];
+
+    run_test(chain, |store, _| {
+        // A truncated hash is reported as an error
+        store.set_chain_head_raw(Some("0xdeadbeef"), Some(1));
+        let err = store.chain_head_ptr().unwrap_err().to_string();
+        assert!(err.contains("0xdeadbeef"));
+        assert!(err.contains(&store.chain));
+
+        // Having only one of hash and number set is an error
+        store.set_chain_head_raw(Some(&BLOCK_ONE.hash), None);
+        assert!(store.chain_head_ptr().is_err());
+        store.set_chain_head_raw(None, Some(1));
+        assert!(store.chain_head_ptr().is_err());
+
+        // A valid head block pointer is still read correctly
+        store.set_chain_head_raw(Some(&BLOCK_ONE.hash), Some(1));
+        assert_eq!(Some(BLOCK_ONE.block_ptr()), store.chain_head_ptr()?);
+
+        store.set_chain_head_raw(None, None);
+        assert_eq!(None, store.chain_head_ptr()?);
+        Ok(())
+    })
+}
+
+#[test]
+fn eth_call_cache_get_calls() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO];
+
+    run_test(chain, |store, _| {
+        let address1 = H160([1; 20]);
+        let address2 = H160([2; 20]);
+        let call1: Vec<u8> = vec![1, 2, 3];
+        let call2: Vec<u8> = vec![4, 5, 6];
+
+        store.set_call(address1, &call1, BLOCK_ONE.block_ptr(), &[7, 8, 9])?;
+        store.set_call(address2, &call2, BLOCK_TWO.block_ptr(), &[10, 11])?;
+
+        let requests = vec![
+            (address1, call1.clone(), BLOCK_ONE.block_ptr()),
+            (address1, call1.clone(), BLOCK_TWO.block_ptr()),
+            (address2, call2.clone(), BLOCK_TWO.block_ptr()),
+            (address2, call1.clone(), BLOCK_TWO.block_ptr()),
+            (address1, call1.clone(), BLOCK_ONE.block_ptr()),
+        ];
+        let expected: Vec<Option<Vec<u8>>> = vec![
+            Some(vec![7, 8, 9]),
+            None,
+            Some(vec![10, 11]),
+            None,
+            Some(vec![7, 8, 9]),
+        ];
+        assert_eq!(expected, store.get_calls(&requests)?);
+
+        // The batch lookup agrees with looking up calls one by one
+        for ((address, call, block), exp) in requests.into_iter().zip(expected) {
+            assert_eq!(exp, store.get_call(address, &call, block)?);
+        }
+
+        assert!(store.get_calls(&[])?.is_empty());
+        Ok(())
+    })
+}
+
+#[test]
+fn eth_call_cache_remove_stale_calls() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO];
+
+    run_test(chain, |store, _| {
+        let fresh = H160([1; 20]);
+        let stale = H160([2; 20]);
+        let call: [u8; 3] = [1, 2, 3];
+
+        store.set_call(fresh, &call, BLOCK_ONE.block_ptr(), &[1])?;
+        store.set_call(stale, &call, BLOCK_ONE.block_ptr(), &[2])?;
+        store.set_call(stale, &call, BLOCK_TWO.block_ptr(), &[3])?;
+
+        store.backdate_call_access(fresh, 2)?;
+        store.backdate_call_access(stale, 10)?;
+
+        // Nothing has been unused for more than 30 days
+        assert_eq!(0, store.remove_stale_calls(30)?);
+
+        assert_eq!(2, store.remove_stale_calls(5)?);
+        assert!(store
+            .get_call(stale, &call, BLOCK_ONE.block_ptr())?
+            .is_none());
+        assert!(store
+            .get_call(stale, &call, BLOCK_TWO.block_ptr())?
+            .is_none());
+        assert_eq!(
+            Some(vec![1]),
+            store.get_call(fresh, &call, BLOCK_ONE.block_ptr())?
+        );
+
+        // Calls that were accessed today are never removed
+        assert_eq!(0, store.remove_stale_calls(0)?);
+        assert!(store
+            .get_call(fresh, &call, BLOCK_ONE.block_ptr())?
+            .is_some());
+        Ok(())
+    })
+}
+
+#[test]
+fn eth_call_cache_set_calls() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO];
+
+    run_test(chain, |store, _| {
+        let address1 = H160([1; 20]);
+        let address2 = H160([2; 20]);
+        let call1: Vec<u8> = vec![1, 2, 3];
+        let call2: Vec<u8> = vec![4, 5, 6];
+
+        // Make both contracts look like they have not been used in a while
+        store.set_call(address1, &call1, GENESIS_BLOCK.block_ptr(), &[1])?;
+        store.set_call(address2, &call2, GENESIS_BLOCK.block_ptr(), &[2])?;
+        store.backdate_call_access(address1, 10)?;
+        store.backdate_call_access(address2, 10)?;
+
+        let calls = vec![
+            (address1, call1.clone(), BLOCK_ONE.block_ptr(), vec![7, 8]),
+            (address1, call2.clone(), BLOCK_ONE.block_ptr(), vec![10]),
+            (address2, call2.clone(), BLOCK_TWO.block_ptr(), vec![11, 12]),
+            // Duplicates and calls that are already cached do not
+            // overwrite what is there
+            (address1, call1.clone(), BLOCK_ONE.block_ptr(), vec![13]),
+            (address1, call1.clone(), GENESIS_BLOCK.block_ptr(), vec![14]),
+        ];
+        store.set_calls(&calls)?;
+
+        let requests: Vec<_> = calls
+            .iter()
+            .map(|(address, call, block, _)| (*address, call.clone(), block.clone()))
+            .collect();
+        let expected = vec![
+            Some(vec![7, 8]),
+            Some(vec![10]),
+            Some(vec![11, 12]),
+            Some(vec![7, 8]),
+            Some(vec![1]),
+        ];
+        assert_eq!(expected, store.get_calls(&requests)?);
+
+        // Both contracts were marked as accessed today
+        assert_eq!(0, store.remove_stale_calls(5)?);
+
+        store.set_calls(&[])?;
+        Ok(())
+    })
+}
+
+#[test]
+fn eth_call_cache_set_call_strict() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO];
+
+    run_test(chain, |store, _| {
+        let address = H160([1; 20]);
+        let call: [u8; 3] = [1, 2, 3];
+
+        store.set_call(address, &call, BLOCK_ONE.block_ptr(), &[7])?;
+
+        // Setting the same value again is fine, but a different one is not
+        store.set_call_strict(address, &call, BLOCK_ONE.block_ptr(), &[7])?;
+        assert!(store
+            .set_call_strict(address, &call, BLOCK_ONE.block_ptr(), &[8])
+            .is_err());
+        assert_eq!(
+            Some(vec![7]),
+            store.get_call(address, &call, BLOCK_ONE.block_ptr())?
+        );
+
+        // Calls that are not cached yet are simply added
+        store.set_call_strict(address, &call, BLOCK_TWO.block_ptr(), &[9])?;
+        assert_eq!(
+            Some(vec![9]),
+            store.get_call(address, &call, BLOCK_TWO.block_ptr())?
+        );
+
+        // The default behavior is still to keep what is cached
+        store.set_call(address, &call, BLOCK_TWO.block_ptr(), &[10])?;
+        assert_eq!(
+            Some(vec![9]),
+            store.get_call(address, &call, BLOCK_TWO.block_ptr())?
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn eth_call_cache_remove_call() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO];
+
+    run_test(chain, |store, _| {
+        let address = H160([1; 20]);
+        let call1: [u8; 3] = [1, 2, 3];
+        let call2: [u8; 3] = [4, 5, 6];
+
+        store.set_call(address, &call1, BLOCK_ONE.block_ptr(), &[7])?;
+        store.set_call(address, &call1, BLOCK_TWO.block_ptr(), &[8])?;
+        store.set_call(address, &call2, BLOCK_ONE.block_ptr(), &[9])?;
+
+        assert!(store.remove_call(address, &call1, BLOCK_ONE.block_ptr())?);
+        assert!(!store.remove_call(address, &call1, BLOCK_ONE.block_ptr())?);
+
+        assert_eq!(
+            None,
+            store.get_call(address, &call1, BLOCK_ONE.block_ptr())?
+        );
+        assert_eq!(
+            Some(vec![8]),
+            store.get_call(address, &call1, BLOCK_TWO.block_ptr())?
+        );
+        assert_eq!(
+            Some(vec![9]),
+            store.get_call(address, &call2, BLOCK_ONE.block_ptr())?
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn block_ptrs_by_numbers() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_TWO,
+        &*BLOCK_TWO_NO_PARENT,
+        &*BLOCK_THREE,
+    ];
+
+    run_test(chain, |store, _| {
+        let mut ptrs = store.block_ptrs_by_numbers(&[1, 2, 4])?;
+        assert_eq!(2, ptrs.len());
+        assert_eq!(Some(vec![BLOCK_ONE.block_ptr()]), ptrs.remove(&1));
+
+        let mut twos = ptrs.remove(&2).expect("blocks with number 2 are present");
+        twos.sort_by_key(|ptr| ptr.hash_hex());
+        let mut exp = vec![BLOCK_TWO.block_ptr(), BLOCK_TWO_NO_PARENT.block_ptr()];
+        exp.sort_by_key(|ptr| ptr.hash_hex());
+        assert_eq!(exp, twos);
+
+        assert!(store.block_ptrs_by_numbers(&[])?.is_empty());
+        Ok(())
+    })
+}
+
+#[test]
+fn delete_blocks_in_range() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_TWO,
+        &*BLOCK_TWO_NO_PARENT,
+        &*BLOCK_THREE,
+    ];
+
+    run_test(chain, |store, _| {
+        // The range straddles the genesis block, which must be kept
+        assert_eq!(3, store.delete_blocks_in_range(0, 2)?);
+
+        assert_eq!(
+            vec![GENESIS_BLOCK.block_hash()],
+            store.block_hashes_by_block_number(0)?
+        );
+        assert!(store.block_hashes_by_block_number(1)?.is_empty());
+        assert!(store.block_hashes_by_block_number(2)?.is_empty());
+        assert_eq!(
+            vec![BLOCK_THREE.block_hash()],
+            store.block_hashes_by_block_number(3)?
+        );
+
+        assert_eq!(0, store.delete_blocks_in_range(4, 10)?);
+        Ok(())
+    })
+}
+
+#[test]
+fn block_exists_and_highest_block_number() {
+    run_test(vec![], |store, _| {
+        assert_eq!(None, store.highest_block_number()?);
+        assert!(!store.block_exists(&GENESIS_BLOCK.block_hash())?);
+        Ok(())
+    });
+
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_TWO,
+        &*BLOCK_THREE_NO_PARENT,
+    ];
+    run_test(chain, |store, _| {
+        assert_eq!(Some(3), store.highest_block_number()?);
+        assert!(store.block_exists(&GENESIS_BLOCK.block_hash())?);
+        assert!(store.block_exists(&BLOCK_THREE_NO_PARENT.block_hash())?);
+        assert!(!store.block_exists(&BLOCK_THREE.block_hash())?);
+        Ok(())
+    })
+}
+
+#[test]
+fn delete_blocks_keeps_genesis() {
+    let chain = vec![&*GENESIS_BLOCK, &*GENESIS_SIBLING, &*BLOCK_ONE, &*BLOCK_TWO];
+
+    run_test(chain, |store, _| {
+        // Blocks are protected because they are the genesis block, not
+        // because they have number 0
+        assert_eq!(2, store.delete_blocks_in_range(0, 1)?);
+        assert_eq!(
+            vec![GENESIS_BLOCK.block_hash()],
+            store.block_hashes_by_block_number(0)?
+        );
+        assert!(store.block_exists(&BLOCK_TWO.block_hash())?);
+        Ok(())
+    })
+}
+
+#[test]
+fn upsert_blocks() {
+    run_test_async(vec![&*GENESIS_BLOCK], |store, _| async move {
+        let blocks: Vec<Arc<dyn Block>> = vec![
+            Arc::new(BLOCK_ONE.clone()),
+            Arc::new(BLOCK_TWO.clone()),
+            Arc::new(BLOCK_THREE.clone()),
+            // Inserting the same block twice in one batch must not fail
+            Arc::new(BLOCK_ONE.clone()),
+        ];
+        store.upsert_blocks(blocks).await.expect("upsert succeeds");
+
+        assert_eq!(Some(3), store.highest_block_number().unwrap());
+        for block in vec![&*BLOCK_ONE, &*BLOCK_TWO, &*BLOCK_THREE] {
+            assert!(store.block_exists(&block.block_hash()).unwrap());
+        }
+        assert_eq!(
+            vec![BLOCK_TWO.block_hash()],
+            store.block_hashes_by_block_number(2).unwrap()
+        );
+
+        // Writing blocks that are already there is fine
+        let blocks: Vec<Arc<dyn Block>> =
+            vec![Arc::new(BLOCK_THREE.clone()), Arc::new(BLOCK_FOUR.clone())];
+        store.upsert_blocks(blocks).await.expect("upsert succeeds");
+        assert_eq!(Some(4), store.highest_block_number().unwrap());
+
+        store
+            .upsert_blocks(vec![])
+            .await
+            .expect("empty upsert succeeds");
+    })
+}
+
+/// A block whose data Postgres refuses to store since a `jsonb` value can
+/// not contain the character `\u0000`
+struct UnstorableBlock(FakeBlock);
+
+impl Block for UnstorableBlock {
+    fn ptr(&self) -> BlockPtr {
+        self.0.ptr()
+    }
+
+    fn parent_ptr(&self) -> Option<BlockPtr> {
+        self.0.parent_ptr()
+    }
+
+    fn data(&self) -> Result<json::Value, json::Error> {
+        Ok(json::json!({ "block": "\u{0}", "transaction_receipts": [] }))
+    }
+}
+
+#[test]
+fn upsert_blocks_is_atomic() {
+    run_test_async(vec![&*GENESIS_BLOCK], |store, _| async move {
+        let blocks: Vec<Arc<dyn Block>> = vec![
+            Arc::new(BLOCK_ONE.clone()),
+            Arc::new(UnstorableBlock(BLOCK_TWO.clone())),
+            Arc::new(BLOCK_THREE.clone()),
+        ];
+        store
+            .upsert_blocks(blocks)
+            .await
+            .expect_err("blocks with a NUL character can not be stored");
+
+        // None of the blocks in the batch were written
+        for block in vec![&*BLOCK_ONE, &*BLOCK_TWO, &*BLOCK_THREE] {
+            assert!(!store.block_exists(&block.block_hash()).unwrap());
+        }
+    })
+}
+
+/// Remembers all operations that a `ChainStore` reports
+#[derive(Default)]
+struct MockMetrics {
+    ops: Mutex<Vec<(String, Duration, usize)>>,
+}
+
+impl ChainStoreMetrics for MockMetrics {
+    fn record_op(&self, op: &str, duration: Duration, rows: usize) {
+        self.ops
+            .lock()
+            .unwrap()
+            .push((op.to_string(), duration, rows));
+    }
+}
+
+#[test]
+fn chain_store_metrics() {
+    run_test_async(vec![&*GENESIS_BLOCK], |store, _| async move {
+        let metrics = Arc::new(MockMetrics::default());
+        store.set_metrics(Some(metrics.clone()));
+
+        store
+            .upsert_block(Arc::new(BLOCK_ONE.clone()))
+            .await
+            .expect("upsert succeeds");
+        store
+            .blocks(&[BLOCK_ONE.block_hash(), BLOCK_TWO.block_hash()])
+            .expect("blocks succeeds");
+        store.set_metrics(None);
+        store
+            .blocks(&[BLOCK_ONE.block_hash()])
+            .expect("blocks succeeds");
+
+        let ops = metrics.ops.lock().unwrap();
+        assert_eq!(2, ops.len());
+        let (op, duration, rows) = &ops[0];
+        assert_eq!("upsert_block", op);
+        assert!(*duration > Duration::from_secs(0));
+        assert_eq!(1, *rows);
+        let (op, _, rows) = &ops[1];
+        assert_eq!("blocks", op);
+        assert_eq!(1, *rows);
+    })
+}
+
+#[test]
+fn confirm_block_hash_reporting() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_TWO,
+        &*BLOCK_TWO_NO_PARENT,
+    ];
+    run_test(chain, move |store, _| {
+        let removed = store.confirm_block_hash_reporting(2, &BLOCK_TWO.block_hash())?;
+        assert_eq!(vec![BLOCK_TWO_NO_PARENT.block_hash()], removed);
+
+        let removed = store.confirm_block_hash_reporting(2, &BLOCK_TWO.block_hash())?;
+        assert!(removed.is_empty());
+
+        assert_eq!(
+            vec![BLOCK_TWO.block_hash()],
+            store.block_hashes_by_block_number(2)?
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn blocks_in_range() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_TWO,
+        &*BLOCK_TWO_NO_PARENT,
+        &*BLOCK_THREE,
+        &*BLOCK_FOUR,
+    ];
+    run_test(chain, move |store, _| {
+        fn hash_of(block: &LightEthereumBlock) -> String {
+            format!("0x{:x}", block.hash.expect("blocks have a hash"))
+        }
+
+        let expected: Vec<_> = vec![
+            &*BLOCK_ONE,
+            &*BLOCK_TWO,
+            &*BLOCK_TWO_NO_PARENT,
+            &*BLOCK_THREE,
+        ]
+        .into_iter()
+        .map(|block| (block.number, format!("0x{}", block.hash)))
+        .collect();
+
+        // The result must not depend on how many blocks we load at once
+        for batch in vec![1, 2, 3, 4, 100] {
+            let actual = store
+                .blocks_in_range(1..4, batch)?
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut hashes: Vec<_> = actual.iter().map(hash_of).collect();
+            let numbers: Vec<_> = hashes
+                .iter()
+                .map(|hash| {
+                    expected
+                        .iter()
+                        .find(|(_, exp)| exp == hash)
+                        .map(|(number, _)| *number)
+                        .expect("only blocks in the range are returned")
+                })
+                .collect();
+            assert_eq!(vec![1, 2, 2, 3], numbers, "batch size {}", batch);
+
+            hashes.dedup();
+            assert_eq!(4, hashes.len(), "batch size {}", batch);
+        }
+
+        assert_eq!(0, store.blocks_in_range(5..10, 2)?.count());
+        assert!(store.blocks_in_range(0..10, 0).is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn find_gaps() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_TWO,
+        &*BLOCK_THREE_NO_PARENT,
+        &*BLOCK_FIVE,
+    ];
+    run_test(chain, move |store, _| {
+        // Block 3 does not connect to block 2, and block 4 is missing
+        let expected = vec![
+            (3, BLOCK_THREE_NO_PARENT.block_hash()),
+            (5, BLOCK_FIVE.block_hash()),
+        ];
+        assert_eq!(expected, store.find_gaps(0, 10)?);
+        assert_eq!(expected[1..].to_vec(), store.find_gaps(4, 5)?);
+        assert!(store.find_gaps(0, 2)?.is_empty());
+        Ok(())
+    })
+}
+
+#[test]
+fn verify_network() {
+    run_test(vec![&*GENESIS_BLOCK], move |store, _| {
+        assert_eq!(NETWORK_VERSION, store.net_version()?);
+        assert_eq!(GENESIS_BLOCK.block_hash(), store.stored_genesis_hash()?);
+
+        let ident = ChainIdentifier {
+            net_version: NETWORK_VERSION.to_owned(),
+            genesis_block_hash: GENESIS_BLOCK.block_hash().into(),
+        };
+        store.verify_network(&ident)?;
+
+        let wrong_version = ChainIdentifier {
+            net_version: "some other network".to_owned(),
+            ..ident.clone()
+        };
+        assert!(store.verify_network(&wrong_version).is_err());
+
+        let wrong_genesis = ChainIdentifier {
+            genesis_block_hash: BLOCK_ONE.block_hash().into(),
+            ..ident
+        };
+        assert!(store.verify_network(&wrong_genesis).is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn create_with_conflicting_identifier() {
+    run_test(vec![&*GENESIS_BLOCK], move |store, _| {
+        let ident = ChainIdentifier {
+            net_version: NETWORK_VERSION.to_owned(),
+            genesis_block_hash: GENESIS_BLOCK.block_hash().into(),
+        };
+        let wrong_version = ChainIdentifier {
+            net_version: "some other network".to_owned(),
+            ..ident.clone()
+        };
+        let err = store.create(&wrong_version).unwrap_err().to_string();
+        assert!(err.contains("some other network"));
+
+        let wrong_genesis = ChainIdentifier {
+            genesis_block_hash: BLOCK_ONE.block_hash().into(),
+            ..ident.clone()
+        };
+        let err = store.create(&wrong_genesis).unwrap_err().to_string();
+        assert!(err.contains(&BLOCK_ONE.hash));
+
+        // The stored identifier is unchanged
+        store.verify_network(&ident)?;
+        Ok(())
+    })
+}
+
+#[test]
+fn cleanup_cached_blocks_with_floor() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_TWO,
+        &*BLOCK_THREE,
+        &*BLOCK_FOUR,
+    ];
+    run_test(chain, move |store, _| {
+        // Without a chain head, only `min_keep` matters
+        assert_eq!(Some((2, 1)), store.cleanup_cached_blocks_with_floor(2, 1)?);
+        assert!(!store.block_exists(&BLOCK_ONE.block_hash())?);
+
+        // With a chain head, we stay `ancestor_count` blocks behind it
+        store.set_chain_head_raw(Some(BLOCK_FOUR.hash.as_str()), Some(4));
+        assert_eq!(Some((3, 1)), store.cleanup_cached_blocks_with_floor(10, 1)?);
+        assert!(!store.block_exists(&BLOCK_TWO.block_hash())?);
+        assert!(store.block_exists(&BLOCK_THREE.block_hash())?);
+
+        // The genesis block is never removed
+        assert_eq!(None, store.cleanup_cached_blocks_with_floor(0, 1)?);
+        assert_eq!(None, store.cleanup_cached_blocks_with_floor(10, 10)?);
+        assert_eq!(Some((4, 1)), store.cleanup_cached_blocks_with_floor(4, 0)?);
+        assert!(store.block_exists(&GENESIS_BLOCK.block_hash())?);
+        assert_eq!(
+            vec![GENESIS_BLOCK.block_hash()],
+            store.block_hashes_by_block_number(0)?
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn prune_to_depth() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_TWO,
+        &*BLOCK_THREE,
+        &*BLOCK_FOUR,
+    ];
+    run_test(chain, move |store, _| {
+        let address = H160([1; 20]);
+        let call: [u8; 3] = [1, 2, 3];
+        for block in vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO, &*BLOCK_THREE] {
+            store.set_call(address, &call, block.block_ptr(), &[7])?;
+        }
+
+        // Without a chain head, nothing is deleted
+        assert_eq!(PruneReport::default(), store.prune_to_depth(1)?);
+        assert!(store.prune_to_depth(-1).is_err());
+
+        // BLOCK_ONE and BLOCK_TWO and the calls against them are deleted.
+        // Calls are only deleted for chains with their own call cache
+        let private = store.chain != FAKE_NETWORK_SHARED;
+        let calls = |count: usize| if private { count } else { 0 };
+        store.set_chain_head_raw(Some(BLOCK_FOUR.hash.as_str()), Some(4));
+        assert_eq!(
+            PruneReport {
+                blocks: 2,
+                calls: calls(2)
+            },
+            store.prune_to_depth(1)?
+        );
+        assert!(!store.block_exists(&BLOCK_TWO.block_hash())?);
+        assert!(store.block_exists(&BLOCK_THREE.block_hash())?);
+        assert_eq!(
+            Some(vec![7]),
+            store.get_call(address, &call, BLOCK_THREE.block_ptr())?
+        );
+
+        // The genesis block and calls against it are never removed
+        assert_eq!(
+            PruneReport {
+                blocks: 1,
+                calls: calls(1)
+            },
+            store.prune_to_depth(0)?
+        );
+        assert!(store.block_exists(&GENESIS_BLOCK.block_hash())?);
+        assert_eq!(
+            Some(vec![7]),
+            store.get_call(address, &call, GENESIS_BLOCK.block_ptr())?
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn check_storage() {
+    run_test(vec![&*GENESIS_BLOCK], |store, _| {
+        store.check_storage()?;
+
+        store.rename_table("call_meta", "call_meta_moved")?;
+        let res = store.check_storage();
+        store.rename_table("call_meta_moved", "call_meta")?;
+
+        if store.chain == FAKE_NETWORK_SHARED {
+            assert!(res.is_ok());
+        } else {
+            let err = res.unwrap_err().to_string();
+            assert!(err.contains("call_meta"));
+            assert!(!err.contains("call_cache"));
+        }
+        store.check_storage()?;
+        Ok(())
+    })
+}
+
+#[test]
+fn chain_head_pointers_for() {
+    run_test_sequentially(|store| async move {
+        for name in vec![NETWORK_NAME, FAKE_NETWORK_SHARED] {
+            block_store::set_chain(vec![&*GENESIS_BLOCK, &*BLOCK_ONE], name);
+        }
+        let block_store = store.block_store();
+        let chain_store = block_store.chain_store(NETWORK_NAME).expect("chain store");
+        chain_store.set_chain_head_raw(Some(BLOCK_ONE.hash.as_str()), Some(1));
+
+        // The other chain does not have a head block and is left out
+        let heads = block_store
+            .chain_head_pointers_for(&[NETWORK_NAME, FAKE_NETWORK_SHARED, "no_such_chain"])
+            .expect("can load chain heads");
+        assert_eq!(
+            vec![NETWORK_NAME],
+            heads.keys().map(String::as_str).collect::<Vec<_>>()
+        );
+        assert_eq!(BLOCK_ONE.block_ptr(), heads[NETWORK_NAME]);
+
+        let heads = block_store
+            .chain_head_pointers_for(&[FAKE_NETWORK_SHARED])
+            .expect("can load chain heads");
+        assert!(heads.is_empty());
+    })
+}
+
+/// A `FakeBlock` that is stored together with transaction receipts
+struct FakeBlockWithReceipts {
+    block: FakeBlock,
+    receipts: json::Value,
+}
+
+impl FakeBlockWithReceipts {
+    fn as_ethereum_block(&self) -> EthereumBlock {
+        let mut block = self.block.as_ethereum_block();
+        block.transaction_receipts =
+            json::from_value(self.receipts.clone()).expect("receipts are valid");
+        block
+    }
+}
+
+impl Block for FakeBlockWithReceipts {
+    fn ptr(&self) -> BlockPtr {
+        self.block.ptr()
+    }
+
+    fn parent_ptr(&self) -> Option<BlockPtr> {
+        self.block.parent_ptr()
+    }
+
+    fn data(&self) -> Result<json::Value, json::Error> {
+        json::to_value(self.as_ethereum_block())
+    }
+}
+
+#[test]
+fn full_blocks() {
+    run_test_async(vec![&*GENESIS_BLOCK], |store, _| async move {
+        let block = FakeBlockWithReceipts {
+            block: BLOCK_ONE.clone(),
+            receipts: json::json!([{
+                "transactionHash": format!("0x{}", BLOCK_TWO.hash),
+                "transactionIndex": "0x0",
+                "blockHash": format!("0x{}", BLOCK_ONE.hash),
+                "blockNumber": "0x1",
+                "cumulativeGasUsed": "0x5208",
+                "gasUsed": "0x5208",
+                "status": "0x1",
+                "logs": [],
+                "logsBloom": format!("0x{}", "00".repeat(256)),
+            }]),
+        };
+        let expected = block.as_ethereum_block();
+        assert_eq!(1, expected.transaction_receipts.len());
+
+        store
+            .upsert_block(Arc::new(block))
+            .await
+            .expect("upsert succeeds");
+
+        // The block comes back with its receipts intact
+        let blocks = store.full_blocks(vec![BLOCK_ONE.block_hash()]).unwrap();
+        assert_eq!(vec![expected], blocks);
+
+        // Blocks without receipts and unknown hashes work, too
+        let blocks = store
+            .full_blocks(vec![GENESIS_BLOCK.block_hash(), BLOCK_TWO.block_hash()])
+            .unwrap();
+        assert_eq!(vec![GENESIS_BLOCK.as_ethereum_block()], blocks);
+    })
+}
+
+#[test]
+fn ingest_cursor() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO];
+
+    run_test(chain, |store, _| {
+        assert_eq!(None, store.ingest_cursor()?);
+
+        store.set_ingest_cursor(BLOCK_ONE.block_ptr())?;
+        assert_eq!(Some(BLOCK_ONE.block_ptr()), store.ingest_cursor()?);
+
+        // The cursor is independent of the chain head
+        store.set_chain_head_raw(Some(&BLOCK_TWO.hash), Some(2));
+        assert_eq!(Some(BLOCK_ONE.block_ptr()), store.ingest_cursor()?);
+
+        store.set_ingest_cursor(BLOCK_TWO.block_ptr())?;
+        assert_eq!(Some(BLOCK_TWO.block_ptr()), store.ingest_cursor()?);
+        assert_eq!(Some(BLOCK_TWO.block_ptr()), store.chain_head_ptr()?);
+        Ok(())
+    })
+}
+
+#[test]
+fn children_of() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_ONE_SIBLING,
+        &*BLOCK_TWO,
+        &*BLOCK_THREE,
+    ];
+
+    run_test(chain, |store, _| {
+        // The genesis block has two competing children
+        let mut exp = vec![BLOCK_ONE.block_ptr(), BLOCK_ONE_SIBLING.block_ptr()];
+        exp.sort_by_key(|ptr| ptr.hash_hex());
+        assert_eq!(exp, store.children_of(&GENESIS_BLOCK.block_hash())?);
+
+        assert_eq!(
+            vec![BLOCK_TWO.block_ptr()],
+            store.children_of(&BLOCK_ONE.block_hash())?
+        );
+        assert!(store
+            .children_of(&BLOCK_ONE_SIBLING.block_hash())?
+            .is_empty());
+        assert!(store.children_of(&BLOCK_THREE.block_hash())?.is_empty());
+        Ok(())
+    })
+}
+
+#[test]
+fn latest_blocks() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_ONE_SIBLING,
+        &*BLOCK_TWO,
+        &*BLOCK_THREE,
+    ];
+
+    run_test(chain, |store, _| {
+        fn latest_hashes(store: &DieselChainStore, n: usize) -> Result<Vec<H256>, Error> {
+            Ok(store
+                .latest_blocks(n)?
+                .into_iter()
+                .map(|block| block.hash.unwrap())
+                .collect())
+        }
+
+        // There is no chain head, but we still get the newest blocks.
+        // Blocks with the same number are ordered by hash
+        assert!(BLOCK_ONE.hash < BLOCK_ONE_SIBLING.hash);
+        assert_eq!(
+            vec![
+                BLOCK_THREE.block_hash(),
+                BLOCK_TWO.block_hash(),
+                BLOCK_ONE.block_hash()
+            ],
+            latest_hashes(&store, 3)?
+        );
+        assert_eq!(
+            vec![
+                BLOCK_THREE.block_hash(),
+                BLOCK_TWO.block_hash(),
+                BLOCK_ONE.block_hash(),
+                BLOCK_ONE_SIBLING.block_hash(),
+                GENESIS_BLOCK.block_hash()
+            ],
+            latest_hashes(&store, 10)?
+        );
+        assert!(latest_hashes(&store, 0)?.is_empty());
+        Ok(())
+    })
+}
+
+#[test]
+fn flush_waits_for_writes() {
+    run_test_async(vec![&*GENESIS_BLOCK], |store, _| async move {
+        let blocks = vec![&*BLOCK_ONE, &*BLOCK_TWO, &*BLOCK_THREE, &*BLOCK_FOUR];
+
+        let upserts = blocks
+            .iter()
+            .map(|block| store.upsert_block(Arc::new((*block).clone())));
+        let upserts = graph::prelude::futures03::future::join_all(upserts);
+
+        // `join!` polls the upserts first so that they are in flight by
+        // the time we call `flush`; once it returns, all of them must be
+        // in the store
+        let flush = async {
+            store.flush().await.expect("flush succeeds");
+            for block in &blocks {
+                assert!(store.block_exists(&block.block_hash()).unwrap());
+            }
+        };
+        let (results, ()) = graph::prelude::tokio::join!(upserts, flush);
+        for result in results {
+            result.expect("upsert succeeds");
+        }
+
+        // Writes after a flush are not affected by it
+        store
+            .upsert_block(Arc::new(BLOCK_FIVE.clone()))
+            .await
+            .expect("upsert succeeds");
+        assert!(store.block_exists(&BLOCK_FIVE.block_hash()).unwrap());
+    })
+}
+
+#[test]
+fn genesis_block() {
+    run_test(vec![], |store, _| {
+        assert_eq!(None, store.genesis_block()?);
+        Ok(())
+    });
+
+    run_test(vec![&*GENESIS_BLOCK, &*BLOCK_ONE], |store, _| {
+        assert_eq!(
+            Some(GENESIS_BLOCK.as_ethereum_block()),
+            store.genesis_block()?
+        );
+        Ok(())
+    })
+}
+
+/// A block that has the timestamp `timestamp`
+struct FakeBlockWithTimestamp {
+    block: FakeBlock,
+    timestamp: U256,
+}
+
+impl Block for FakeBlockWithTimestamp {
+    fn ptr(&self) -> BlockPtr {
+        self.block.ptr()
+    }
+
+    fn parent_ptr(&self) -> Option<BlockPtr> {
+        self.block.parent_ptr()
+    }
+
+    fn data(&self) -> Result<json::Value, json::Error> {
+        let mut block = self.block.as_ethereum_block();
+        Arc::make_mut(&mut block.block).timestamp = self.timestamp;
+        json::to_value(block)
+    }
+}
+
+#[test]
+fn block_time() {
+    run_test_async(vec![&*GENESIS_BLOCK], |store, _| async move {
+        let block = FakeBlockWithTimestamp {
+            block: BLOCK_ONE.clone(),
+            timestamp: U256::from(1_626_868_800u64),
+        };
+        store
+            .upsert_block(Arc::new(block))
+            .await
+            .expect("upsert succeeds");
+
+        // Without a chain head, there is no canonical chain
+        assert_eq!(None, store.block_time(1).unwrap());
+
+        store.set_chain_head_raw(Some(BLOCK_ONE.hash.as_str()), Some(1));
+        assert_eq!(Some(1_626_868_800), store.block_time(1).unwrap());
+        assert_eq!(Some(0), store.block_time(0).unwrap());
+        assert_eq!(None, store.block_time(2).unwrap());
+    })
+}
+
+#[test]
+fn block_time_out_of_range() {
+    run_test_async(vec![&*GENESIS_BLOCK], |store, _| async move {
+        let block = FakeBlockWithTimestamp {
+            block: BLOCK_ONE.clone(),
+            timestamp: U256::from(u64::MAX) + 1,
+        };
+        store
+            .upsert_block(Arc::new(block))
+            .await
+            .expect("upsert succeeds");
+        store.set_chain_head_raw(Some(BLOCK_ONE.hash.as_str()), Some(1));
+
+        let err = store
+            .block_time(1)
+            .expect_err("timestamps beyond u64::MAX are rejected");
+        assert!(err.to_string().contains("invalid timestamp"), "{}", err);
+    })
+}
+
+/// A block whose data can not be deserialized into an `EthereumBlock`
+struct CorruptBlock(FakeBlock);
+
+impl Block for CorruptBlock {
+    fn ptr(&self) -> BlockPtr {
+        self.0.ptr()
+    }
+
+    fn parent_ptr(&self) -> Option<BlockPtr> {
+        self.0.parent_ptr()
+    }
+
+    fn data(&self) -> Result<json::Value, json::Error> {
+        Ok(json::json!({ "block": "not a block", "transaction_receipts": [] }))
+    }
+}
+
+#[test]
+fn full_blocks_with_corrupt_data() {
+    run_test_async(vec![&*GENESIS_BLOCK], |store, _| async move {
+        store
+            .upsert_block(Arc::new(CorruptBlock(BLOCK_ONE.clone())))
+            .await
+            .expect("upsert succeeds");
+
+        let err = store
+            .full_blocks(vec![BLOCK_ONE.block_hash()])
+            .expect_err("corrupt blocks can not be deserialized");
+        match err.downcast_ref::<StoreError>() {
+            Some(StoreError::Deserialization { .. }) => (),
+            _ => panic!("expected a deserialization error but got {:?}", err),
+        }
+    })
+}
+
+#[test]
+fn reorg_to() {
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE,
+        &*BLOCK_TWO,
+        &*BLOCK_TWO_NO_PARENT,
+        &*BLOCK_THREE,
+        &*BLOCK_FOUR,
+        &*BLOCK_FIVE,
+    ];
+
+    run_test_async(chain, |store, _| async move {
+        store.set_chain_head_raw(Some(BLOCK_FIVE.hash.as_str()), Some(5));
+
+        // We can not reorg to a block we do not have
+        store
+            .reorg_to(BLOCK_SIX_NO_PARENT.block_ptr())
+            .await
+            .expect_err("reorg to an unknown block fails");
+        assert_eq!(
+            Some(BLOCK_FIVE.block_ptr()),
+            store.chain_head_ptr().unwrap()
+        );
+        assert!(store.block_exists(&BLOCK_FIVE.block_hash()).unwrap());
+
+        let mut removed = store
+            .reorg_to(BLOCK_TWO.block_ptr())
+            .await
+            .expect("reorg succeeds");
+        removed.sort();
+        let mut exp = vec![
+            BLOCK_TWO_NO_PARENT.block_hash(),
+            BLOCK_THREE.block_hash(),
+            BLOCK_FOUR.block_hash(),
+            BLOCK_FIVE.block_hash(),
+        ];
+        exp.sort();
+        assert_eq!(exp, removed);
+
+        assert_eq!(Some(BLOCK_TWO.block_ptr()), store.chain_head_ptr().unwrap());
+        assert_eq!(
+            vec![BLOCK_TWO.block_hash()],
+            store.block_hashes_by_block_number(2).unwrap()
+        );
+        assert_eq!(Some(2), store.highest_block_number().unwrap());
+        assert!(store.block_exists(&BLOCK_ONE.block_hash()).unwrap());
+    })
+}
+
+#[test]
+fn statement_timeout() {
+    use diesel::connection::SimpleConnection;
+
+    run_test_async(vec![&*GENESIS_BLOCK, &*BLOCK_ONE], |store, _| async move {
+        // Hold a lock that the chain head update needs so that its queries
+        // have to wait until they time out
+        store.set_statement_timeout(Some(Duration::from_millis(100)));
+        let conn = primary_pg_connection();
+        conn.batch_execute("begin; lock table ethereum_networks in access exclusive mode")
+            .unwrap();
+        let res = store
+            .clone()
+            .chain_head_update_preview(ANCESTOR_COUNT)
+            .await;
+        conn.batch_execute("rollback").unwrap();
+
+        let err = res.expect_err("waiting for the lock times out");
+        match err.downcast_ref::<StoreError>() {
+            Some(StoreError::StatementTimeout) => (),
+            _ => panic!("expected a statement timeout but got {:?}", err),
+        }
+
+        // Without the lock, the same queries finish in time
+        let preview = store
+            .clone()
+            .chain_head_update_preview(ANCESTOR_COUNT)
+            .await
+            .expect("chain_head_update_preview succeeds");
+        assert_eq!(Some(BLOCK_ONE.block_ptr()), preview.candidate);
+        store.set_statement_timeout(None);
+    })
+}
+
+#[test]
+fn connection_timeout() {
+    fn is_timeout(err: &Error) -> bool {
+        matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::ConnectionTimeout)
+        )
+    }
+
+    run_test_async(vec![&*GENESIS_BLOCK], |store, _| async move {
+        // Hold the only connection of the pool that the store uses so that
+        // it has to give up waiting for one
+        let pool = primary_pool_with_size(1);
+        let store = store.with_pool(pool.clone(), Duration::from_millis(100));
+        let conn = pool.get().unwrap();
+
+        let res = store.check_storage();
+        assert!(
+            matches!(res, Err(StoreError::ConnectionTimeout)),
+            "expected a connection timeout but got {:?}",
+            res
+        );
+
+        // Writes, both the ones that run on the blocking pool and the ones
+        // that use a connection directly, time out the same way
+        let err = store
+            .upsert_block(Arc::new(BLOCK_ONE.clone()))
+            .await
+            .expect_err("upsert_block times out");
+        assert!(is_timeout(&err), "unexpected error {:?}", err);
+        let err = store
+            .upsert_light_blocks(&[&*BLOCK_ONE as &dyn Block])
+            .expect_err("upsert_light_blocks times out");
+        assert!(is_timeout(&err), "unexpected error {:?}", err);
+        drop(conn);
+
+        // Once the connection is back in the pool, the store can use it
+        store.check_storage().unwrap();
+    })
+}
@@ -1839,13 +1839,6 @@ fn find_at_block() {
 
 #[test]
 fn cleanup_cached_blocks() {
-    if store_is_sharded() {
-        // When the store is sharded, the setup in main.rs makes sure we
-        // don't ever try to clean up cached blocks
-        println!("store is sharded, skipping test");
-        return;
-    }
-
     run_test(|store, _, _| async move {
         use block_store::*;
         // The test subgraph is at block 2. Since we don't ever delete
@@ -17,6 +17,10 @@ lazy_static! {
         hash: super::GENESIS_PTR.hash_hex(),
         parent_hash: NO_PARENT.to_string()
     };
+    pub static ref GENESIS_SIBLING: FakeBlock = FakeBlock::make_no_parent(
+        0,
+        "2a3c1e4d8f6b9a0c5e7d3f1b4a6c8e0d2f4b6a8c0e2d4f6b8a0c2e4d6f8b0a1c"
+    );
     pub static ref BLOCK_ONE: FakeBlock = GENESIS_BLOCK
         .make_child("8511fa04b64657581e3f00e14543c1d522d5d7e771b54aa3060b662ade47da13");
     pub static ref BLOCK_ONE_SIBLING: FakeBlock =
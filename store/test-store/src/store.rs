@@ -12,14 +12,17 @@ use graph::{
     components::store::StoredDynamicDataSource, data::subgraph::status, prelude::NodeId,
 };
 use graph_graphql::prelude::{
-    execute_query, Query as PreparedQuery, QueryExecutionOptions, StoreResolver,
+    execute_query, Query as PreparedQuery, QueryExecutionOptions, ScalarCoercions, StoreResolver,
 };
 use graph_graphql::test_support::ResultSizeMetrics;
 use graph_mock::MockMetricsRegistry;
 use graph_node::config::{Config, Opt};
 use graph_node::store_builder::StoreBuilder;
 use graph_store_postgres::layout_for_tests::FAKE_NETWORK_SHARED;
-use graph_store_postgres::{connection_pool::ConnectionPool, Shard, SubscriptionManager};
+use graph_store_postgres::{
+    connection_pool::{ConnectionPool, PoolName},
+    Shard, SubscriptionManager,
+};
 use graph_store_postgres::{
     BlockStore as DieselBlcokStore, DeploymentPlacer, SubgraphStore as DieselSubgraphStore,
     PRIMARY_SHARD,
@@ -211,24 +214,41 @@ pub async fn transact_errors(
     block_ptr_to: BlockPtr,
     errs: Vec<SubgraphError>,
 ) -> Result<(), StoreError> {
+    transact_entities_and_errors(store, deployment, block_ptr_to, Vec::new(), errs).await
+}
+
+/// Transact `ops` and record the non-fatal errors `errs` in the same block
+pub async fn transact_entities_and_errors(
+    store: &Arc<Store>,
+    deployment: &DeploymentLocator,
+    block_ptr_to: BlockPtr,
+    ops: Vec<EntityOperation>,
+    errs: Vec<SubgraphError>,
+) -> Result<(), StoreError> {
+    let store = store
+        .subgraph_store()
+        .writable(LOGGER.clone(), deployment.id.clone())
+        .await?;
+    let mut entity_cache = EntityCache::new(store.clone());
+    entity_cache.append(ops);
+    let mods = entity_cache
+        .as_modifications()
+        .expect("failed to convert to modifications")
+        .modifications;
     let metrics_registry = Arc::new(MockMetricsRegistry::new());
     let stopwatch_metrics = StopwatchMetrics::new(
         Logger::root(slog::Discard, o!()),
         deployment.hash.clone(),
         metrics_registry.clone(),
     );
-    store
-        .subgraph_store()
-        .writable(LOGGER.clone(), deployment.id.clone())
-        .await?
-        .transact_block_operations(
-            block_ptr_to,
-            None,
-            Vec::new(),
-            stopwatch_metrics,
-            Vec::new(),
-            errs,
-        )
+    store.transact_block_operations(
+        block_ptr_to,
+        None,
+        mods,
+        stopwatch_metrics,
+        Vec::new(),
+        errs,
+    )
 }
 
 /// Convenience to transact EntityOperation instead of EntityModification
@@ -409,7 +429,10 @@ async fn execute_subgraph_query_internal(
         .query_store(deployment.into(), false)
         .await
         .unwrap();
-    for (bc, (selection_set, error_policy)) in return_err!(query.block_constraint()) {
+    let scalar_coercions = Arc::new(ScalarCoercions::default());
+    for (bc, (selection_set, error_policy)) in
+        return_err!(query.block_constraint(&scalar_coercions))
+    {
         let logger = logger.clone();
         let resolver = return_err!(
             StoreResolver::at_block(
@@ -434,6 +457,10 @@ async fn execute_subgraph_query_internal(
                     load_manager: LOAD_MANAGER.clone(),
                     max_first: std::u32::MAX,
                     max_skip: std::u32::MAX,
+                    trace: false,
+                    list_concurrency: 1,
+                    allow_introspection: true,
+                    scalar_coercions: scalar_coercions.cheap_clone(),
                 },
             )
             .await,
@@ -517,6 +544,34 @@ pub fn primary_connection() -> graph_store_postgres::layout_for_tests::Connectio
     graph_store_postgres::layout_for_tests::Connection::new(conn)
 }
 
+/// Get a plain connection to the primary database for tests that need to
+/// run their own SQL alongside a store
+pub fn primary_pg_connection(
+) -> diesel::r2d2::PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>> {
+    PRIMARY_POOL.get().unwrap()
+}
+
+/// Create a separate pool with `size` connections to the primary database.
+/// The pool skips setup since `STORE` already set up the database
+pub fn primary_pool_with_size(size: u32) -> ConnectionPool {
+    let shard = CONFIG
+        .stores
+        .get(PRIMARY_SHARD.as_str())
+        .expect("there is a primary shard");
+    let pool = ConnectionPool::create(
+        PRIMARY_SHARD.as_str(),
+        PoolName::Main,
+        shard.connection.clone(),
+        size,
+        None,
+        &*LOGGER,
+        METRICS_REGISTRY.clone(),
+        Arc::new(Vec::new()),
+    );
+    pool.skip_setup();
+    pool
+}
+
 pub fn primary_mirror() -> graph_store_postgres::layout_for_tests::Mirror {
     let pool = PRIMARY_POOL.clone();
     let map = HashMap::from_iter(Some((PRIMARY_SHARD.clone(), pool)));